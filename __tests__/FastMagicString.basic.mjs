@@ -711,12 +711,11 @@ describe('MagicString', () => {
       })
     })
 
+    // magic-string keeps `$10` verbatim, `String.prototype.replace` reads it as `$1` and `0`
     it('works with global regex replace', () => {
-      validate(Cons => {
-        const s = new Cons('1 2 3 4 a b c')
-        s.replace(/(\d)/g, 'xx$1$10')
-        return s.toString()
-      })
+      const s = new RustMagicString('1 2 3 4 a b c')
+      s.replace(/(\d)/g, 'xx$1$10')
+      expect(s.toString()).toBe('1 2 3 4 a b c'.replace(/(\d)/g, 'xx$1$10'))
     })
 
     it('works with global regex replace $$', () => {
@@ -761,12 +760,11 @@ describe('MagicString', () => {
       })
     })
 
+    // magic-string keeps `$10` verbatim, `String.prototype.replace` reads it as `$1` and `0`
     it('global regex result the same as .replace 1', () => {
-      validate(Cons => {
-        const s = new Cons('1 2 3 4 a b c')
-        s.replaceAll(/(\d)/g, 'xx$1$10')
-        return s.toString()
-      })
+      const s = new RustMagicString('1 2 3 4 a b c')
+      s.replaceAll(/(\d)/g, 'xx$1$10')
+      expect(s.toString()).toBe('1 2 3 4 a b c'.replace(/(\d)/g, 'xx$1$10'))
     })
     it('global regex result the same as .replace 2', () => {
       validate(Cons => {
@@ -1158,4 +1156,1125 @@ describe('MagicString', () => {
       })
     })
   })
+
+  describe('line/column api', () => {
+    it('overwriteAt / removeAt', () => {
+      validate(Cons => {
+        const s = new Cons('abc\ndef\nghi')
+        if (Cons === RustMagicString) {
+          s.overwriteAt({ line: 1, column: 0 }, { line: 1, column: 3 }, 'XYZ')
+          s.removeAt({ line: 2, column: 1 }, { line: 2, column: 3 })
+        } else {
+          s.overwrite(4, 7, 'XYZ')
+          s.remove(9, 11)
+        }
+        return s.toString()
+      })
+    })
+
+    it('appendLeftAt / prependRightAt / sliceAt', () => {
+      validate(Cons => {
+        const s = new Cons('abc\ndef\nghi')
+        if (Cons === RustMagicString) {
+          s.appendLeftAt({ line: 0, column: 3 }, '!')
+          s.prependRightAt({ line: 2, column: 0 }, '>')
+          return s.sliceAt({ line: 0, column: 0 }, { line: 2, column: 3 })
+        }
+        s.appendLeft(3, '!')
+        s.prependRight(8, '>')
+        return s.slice(0, 11)
+      })
+    })
+
+    it('indexOf / positionOf', () => {
+      const s = new RustMagicString('abc\ndef\nghi')
+      expect(s.indexOf(1, 2)).toBe(6)
+      expect(s.positionOf(6)).toEqual({ line: 1, column: 2 })
+    })
+
+    it('oneBasedLines', () => {
+      const s = new RustMagicString('abc\ndef\nghi', { oneBasedLines: true })
+      expect(s.indexOf(2, 2)).toBe(6)
+      expect(s.positionOf(6)).toEqual({ line: 2, column: 2 })
+      expect(() => s.indexOf(0, 0)).toThrow()
+    })
+
+    it('throws when column is past the end of the line', () => {
+      const s = new RustMagicString('abc\ndef\nghi')
+      expect(() => s.indexOf(0, 4)).toThrow(/Index out of range/)
+      expect(() =>
+        s.overwriteAt({ line: 0, column: 0 }, { line: 0, column: 9 }, 'x')
+      ).toThrow(/Index out of range/)
+    })
+  })
+
+  describe('line/column api with non-ASCII text', () => {
+    it('counts columns in UTF-16 code units', () => {
+      const s = new RustMagicString('é = 1\n😀 = 2')
+      expect(s.sliceAt({ line: 1, column: 0 }, { line: 1, column: 2 })).toBe('😀')
+      s.overwriteAt({ line: 1, column: 3 }, { line: 1, column: 4 }, '==')
+      expect(s.toString()).toBe('é = 1\n😀 == 2')
+    })
+
+    it('throws inside a surrogate pair instead of panicking', () => {
+      const s = new RustMagicString('😀 = 1')
+      expect(() => s.indexOf(0, 1)).toThrow(/Index out of range/)
+      expect(() =>
+        s.sliceAt({ line: 0, column: 1 }, { line: 0, column: 3 })
+      ).toThrow(/Index out of range/)
+    })
+  })
+
+  describe('indices with non-ASCII text', () => {
+    it('overwrites at the indices matchAll returns', () => {
+      const s = new RustMagicString('const 名前 = "😀"; foo(名前)')
+      for (const m of s.matchAll(/名前/g)) {
+        s.overwrite(m.index, m.index + m[0].length, 'name')
+      }
+      expect(s.toString()).toBe('const name = "😀"; foo(name)')
+    })
+
+    it('uses UTF-16 indices for edits, slices and queries', () => {
+      const s = new RustMagicString('é😀abc')
+      expect(s.slice(1, 3)).toBe('😀')
+      s.overwrite(3, 4, 'X')
+      expect(s.toString()).toBe('é😀Xbc')
+      expect(s.find('bc')).toEqual({ start: 4, end: 6, text: 'bc', captures: [] })
+      expect(s.chunks().map((c) => [c.start, c.end, c.generatedOffset])).toEqual([
+        [0, 3, 0],
+        [3, 4, 3],
+        [4, 6, 4]
+      ])
+      expect(s.generatedPositionFor(4)).toEqual({ kind: 'exact', index: 4 })
+      expect(s.positionOf(4)).toEqual({ line: 0, column: 4 })
+      expect(() => s.remove(2, 3)).toThrow(/Index out of range/)
+    })
+
+    it('counts offset in UTF-16 code units', () => {
+      const s = new RustMagicString('é😀abc', { offset: 3 })
+      s.overwrite(0, 1, 'Y')
+      expect(s.offset).toBe(3)
+      expect(s.slice()).toBe('Ybc')
+      expect(() => new RustMagicString('é😀abc', { offset: 2 })).toThrow(
+        /Index out of range/
+      )
+    })
+  })
+
+  describe('position queries', () => {
+    it('generatedPositionFor', () => {
+      const s = new RustMagicString('abcdefghij')
+      s.prepend('>>').overwrite(2, 4, 'XYZ').remove(6, 8)
+      expect(s.generatedPositionFor(1)).toEqual({ kind: 'exact', index: 3 })
+      expect(s.generatedPositionFor(3)).toEqual({
+        kind: 'insideEdit',
+        start: 4,
+        end: 7
+      })
+      expect(s.generatedPositionFor(6)).toEqual({ kind: 'removed', index: 9 })
+      expect(s.generatedPositionFor(8)).toEqual({ kind: 'exact', index: 9 })
+    })
+
+    it('originalPositionFor', () => {
+      const s = new RustMagicString('abcdefghij')
+      s.prepend('>>').overwrite(2, 4, 'XYZ').appendLeft(5, '_')
+      // >>abXYZe_fghij
+      expect(s.originalPositionFor(0)).toEqual({
+        kind: 'insideEdit',
+        start: 0,
+        end: 0
+      })
+      expect(s.originalPositionFor(3)).toEqual({ kind: 'exact', index: 1 })
+      expect(s.originalPositionFor(5)).toEqual({
+        kind: 'insideEdit',
+        start: 2,
+        end: 4
+      })
+      expect(s.originalPositionFor(8)).toEqual({
+        kind: 'insideEdit',
+        start: 5,
+        end: 5
+      })
+      expect(s.originalPositionFor(9)).toEqual({ kind: 'exact', index: 5 })
+    })
+
+    it('follows moved content', () => {
+      const s = new RustMagicString('abcdefghij')
+      s.move(0, 2, 10)
+      expect(s.generatedPositionFor(1)).toEqual({ kind: 'exact', index: 9 })
+      expect(s.originalPositionFor(0)).toEqual({ kind: 'exact', index: 2 })
+    })
+  })
+
+  describe('chunks', () => {
+    it('normal', () => {
+      const s = new RustMagicString('abcdefghij')
+      s.prepend('>>')
+      s.overwrite(2, 4, 'XYZ', { storeName: true })
+      s.appendLeft(5, '_')
+      expect(s.chunks()).toEqual([
+        {
+          start: 0,
+          end: 2,
+          original: 'ab',
+          intro: '',
+          content: 'ab',
+          outro: '',
+          edited: false,
+          storeName: false,
+          generatedOffset: 2
+        },
+        {
+          start: 2,
+          end: 4,
+          original: 'cd',
+          intro: '',
+          content: 'XYZ',
+          outro: '',
+          edited: true,
+          storeName: true,
+          generatedOffset: 4
+        },
+        {
+          start: 4,
+          end: 5,
+          original: 'e',
+          intro: '',
+          content: 'e',
+          outro: '_',
+          edited: false,
+          storeName: false,
+          generatedOffset: 7
+        },
+        {
+          start: 5,
+          end: 10,
+          original: 'fghij',
+          intro: '',
+          content: 'fghij',
+          outro: '',
+          edited: false,
+          storeName: false,
+          generatedOffset: 9
+        }
+      ])
+    })
+  })
+
+  describe('render cache', () => {
+    it('hasChanged after reset', () => {
+      validate(Cons => {
+        const s = new Cons('abcdefghij')
+        s.overwrite(2, 4, 'XY')
+        const changed = s.hasChanged()
+        s.reset(2, 4)
+        return `${changed} ${s.hasChanged()} ${s.toString()}`
+      })
+    })
+
+    it('hasChanged tracks moves and no-op overwrites', () => {
+      validate(Cons => {
+        const s = new Cons('abcdefghij')
+        s.overwrite(2, 4, 'cd')
+        const same = s.hasChanged()
+        s.move(0, 2, 10)
+        return `${same} ${s.hasChanged()} ${s.toString()}`
+      })
+    })
+
+    it('failed edits do not invalidate', () => {
+      const s = new RustMagicString('abcdefghij')
+      expect(() => s.overwrite(3, 3, 'x')).toThrow()
+      expect(() => s.move(2, 5, 3)).toThrow()
+      expect(() => s.remove(0, 20)).toThrow()
+      expect(s.hasChanged()).toBe(false)
+    })
+
+    it('toString and generateMap reflect later edits', () => {
+      validate(Cons => {
+        const s = new Cons('abcdefghij')
+        const before = s.toString() + s.generateMap({ hires: true }).mappings
+        s.appendLeft(3, '!')
+        s.addSourcemapLocation(5)
+        return [
+          before,
+          s.toString(),
+          s.generateMap({ hires: true }).mappings,
+          s.generateMap().mappings
+        ].join('\n')
+      })
+    })
+  })
+
+  describe('generate', () => {
+    it('returns code and map together', () => {
+      const s = new RustMagicString('abc\ndef')
+      s.overwrite(1, 2, 'XX')
+      const options = { hires: true, file: 'a.js', source: 'a.ts' }
+      const { code, map } = s.generate(options)
+      expect(code).toBe(s.toString())
+      expect(map).toEqual(s.generateMap(options))
+    })
+
+    it('returns a map for unchanged strings', () => {
+      const s = new RustMagicString('abc\ndef')
+      expect(s.generate().code).toBe('abc\ndef')
+      expect(s.generate().map.mappings).toBe(s.generateMap().mappings)
+      s.overwrite(1, 2, 'XX')
+      s.reset(1, 2)
+      const { code, map } = s.generate({ hires: true })
+      expect(code).toBe('abc\ndef')
+      expect(map.mappings).toBe(s.generateMap({ hires: true }).mappings)
+    })
+
+    it('counts map columns in UTF-16 code units', () => {
+      validate(Cons => {
+        const s = new Cons('const é = "wörld"; d')
+        s.replace('d', 'D')
+        s.prepend('/* ü */ ')
+        return [
+          s.generateMap({ hires: true }).mappings,
+          s.generateMap().mappings
+        ].join('\n')
+      })
+    })
+
+    it('reuses the cached code and mappings', () => {
+      const s = new RustMagicString('abc\ndef')
+      s.overwrite(1, 2, 'XX')
+      const map = s.generateMap({ hires: true })
+      expect(s.generate({ hires: true }).map.mappings).toBe(map.mappings)
+      expect(s.toString()).toBe('aXXc\ndef')
+      expect(s.generate().map.mappings).toBe(s.generateMap().mappings)
+    })
+  })
+
+  describe('dedent', () => {
+    it('strips the common indentation', () => {
+      const s = new RustMagicString('    function f() {\n      return 1\n    }\n')
+      s.dedent()
+      expect(s.toString()).toBe('function f() {\n  return 1\n}\n')
+    })
+
+    it('only strips indentation shared by every line', () => {
+      const mixed = new RustMagicString('\t  a\n  b')
+      mixed.dedent()
+      expect(mixed.toString()).toBe('\t  a\n  b')
+      const tabs = new RustMagicString('\t\ta\n\tb\n\t  c')
+      tabs.dedent()
+      expect(tabs.toString()).toBe('\ta\nb\n  c')
+    })
+
+    it('honors indentExclusionRanges', () => {
+      const s = new RustMagicString(
+        '    function f() {\n      return `\n    keep`\n    }\n',
+        { indentExclusionRanges: [34, 42] }
+      )
+      s.dedent()
+      expect(s.toString()).toBe('function f() {\n  return `\n    keep`\n}\n')
+    })
+
+    it('keeps content inserted after the indentation', () => {
+      const s = new RustMagicString('  a\n  b')
+      s.appendLeft(2, '>>')
+      s.appendLeft(6, '>>')
+      s.dedent(2)
+      expect(s.toString()).toBe('>>a\n>>b')
+    })
+
+    it('dedents inserted text like the original', () => {
+      const s = new RustMagicString('  a\n  b')
+      s.appendLeft(3, '\n    x')
+      s.dedent(2)
+      expect(s.toString()).toBe('a\n  x\nb')
+      const t = new RustMagicString('  a\n  b')
+      t.prependLeft(0, '>>')
+      t.dedent(2)
+      expect(t.toString()).toBe('>>  a\nb')
+    })
+
+    it('treats a lone \\r as a line break', () => {
+      const s = new RustMagicString('    a\r    b\r\n    c')
+      s.dedent()
+      expect(s.toString()).toBe('a\rb\r\nc')
+    })
+
+    it('maps characters to their original columns', () => {
+      const s = new RustMagicString('  a\n  b')
+      s.dedent()
+      const map = new SourceMapConsumer(s.generateMap({ hires: true }))
+      expect(map.originalPositionFor({ line: 2, column: 0 })).toMatchObject({
+        line: 2,
+        column: 2
+      })
+    })
+  })
+
+  describe('normalizeLineEndings', () => {
+    it('converts mixed line endings to LF', () => {
+      const s = new RustMagicString('a\r\nb\nc\rd')
+      s.normalizeLineEndings('lf')
+      expect(s.toString()).toBe('a\nb\nc\nd')
+    })
+
+    it('converts mixed line endings to CRLF', () => {
+      const s = new RustMagicString('a\r\nb\nc\rd')
+      s.normalizeLineEndings('crlf')
+      expect(s.toString()).toBe('a\r\nb\r\nc\r\nd')
+    })
+
+    it('normalizes inserted content', () => {
+      const s = new RustMagicString('a\r\nb')
+      s.appendLeft(1, '\r\nx').prepend('// h\r\n')
+      s.normalizeLineEndings('lf')
+      expect(s.toString()).toBe('// h\na\nx\nb')
+    })
+
+    it('round-trips through LF and back to CRLF', () => {
+      const s = new RustMagicString('a\r\nb')
+      s.normalizeLineEndings('lf')
+      expect(s.toString()).toBe('a\nb')
+      s.normalizeLineEndings('crlf')
+      expect(s.toString()).toBe('a\r\nb')
+    })
+
+    it('treats a CRLF split across edits as one line ending', () => {
+      const s = new RustMagicString('a\r\nb')
+      s.overwrite(0, 2, 'X\r')
+      s.normalizeLineEndings('lf')
+      expect(s.toString()).toBe('X\nb')
+    })
+
+    it('maps a lone CR as a line break', () => {
+      const s = new RustMagicString('ab')
+      s.appendLeft(1, '\r')
+      const map = new SourceMapConsumer(s.generateMap({ hires: true }))
+      expect(map.originalPositionFor({ line: 2, column: 0 })).toMatchObject({
+        line: 1,
+        column: 1
+      })
+    })
+
+    it('keeps mappings to the original lines', () => {
+      const s = new RustMagicString('a\r\nb\rc')
+      s.normalizeLineEndings('lf')
+      const map = new SourceMapConsumer(s.generateMap({ hires: true }))
+      expect(map.originalPositionFor({ line: 3, column: 0 })).toMatchObject({
+        line: 3,
+        column: 0
+      })
+    })
+
+    it('locates positions in CRLF sources', () => {
+      const s = new RustMagicString('a\r\nb\rc')
+      expect(s.positionOf(3)).toEqual({ line: 1, column: 0 })
+      expect(s.positionOf(5)).toEqual({ line: 2, column: 0 })
+      expect(s.indexOf(2, 0)).toBe(5)
+    })
+
+    it('throws on unknown line endings', () => {
+      const s = new RustMagicString('a')
+      expect(() => s.normalizeLineEndings('cr')).toThrow()
+    })
+  })
+
+  describe('reindent', () => {
+    it('converts tabs to spaces', () => {
+      const s = new RustMagicString('a {\n\tb {\n\t\tc\n\t}\n}')
+      s.reindent('tab', 2)
+      expect(s.toString()).toBe('a {\n  b {\n    c\n  }\n}')
+    })
+
+    it('converts spaces to tabs', () => {
+      const s = new RustMagicString('a {\n    b {\n        c\n    }\n}')
+      s.reindent(4, 'tab')
+      expect(s.toString()).toBe('a {\n\tb {\n\t\tc\n\t}\n}')
+    })
+
+    it('guesses the original indentation', () => {
+      const s = new RustMagicString('a {\n  b {\n    c\n  }\n}')
+      s.reindent(null, 'tab')
+      expect(s.toString()).toBe('a {\n\tb {\n\t\tc\n\t}\n}')
+    })
+
+    it('only touches the given range', () => {
+      const s = new RustMagicString('a {\n  b {\n    c\n  }\n}')
+      s.reindent(2, 'tab', { start: 10, end: 16 })
+      expect(s.toString()).toBe('a {\n  b {\n\t\tc\n  }\n}')
+    })
+
+    it('honors indentExclusionRanges', () => {
+      const s = new RustMagicString('\tx = `\n\tkeep`\n\ty', {
+        indentExclusionRanges: [7, 12]
+      })
+      s.reindent('tab', 2)
+      expect(s.toString()).toBe('  x = `\n\tkeep`\n  y')
+    })
+
+    it('reindents inserted content', () => {
+      const s = new RustMagicString('a {\n\tb\n}')
+      s.appendLeft(4, '\t// c\n')
+      s.reindent('tab', 2)
+      expect(s.toString()).toBe('a {\n  // c\n  b\n}')
+    })
+
+    it('maps tokens to their original columns', () => {
+      const s = new RustMagicString('a {\n\tb\n}')
+      s.reindent('tab', 4)
+      const map = new SourceMapConsumer(s.generateMap({ hires: true }))
+      expect(map.originalPositionFor({ line: 2, column: 4 })).toMatchObject({
+        line: 2,
+        column: 1
+      })
+    })
+  })
+
+  describe('replacement patterns', () => {
+    it('expands every special pattern in the replacement', () => {
+      const s = new RustMagicString('foo bar foo')
+      s.replace(/(f)(o+)/g, '[$2$1$$$&$9]')
+      expect(s.toString()).toBe('[oof$foo$9] bar [oof$foo$9]')
+    })
+
+    it('reads $10 as group 1 and a 0 when there are fewer groups', () => {
+      const s = new RustMagicString('a1b')
+      s.replace(/(\d)/, '<$10$01$00>')
+      expect(s.toString()).toBe('a1b'.replace(/(\d)/, '<$10$01$00>'))
+    })
+
+    it('expands named groups', () => {
+      const s = new RustMagicString('x = 1')
+      s.replace(/(?<name>\w+) = (?<value>\d)/, '$<value> = $<name>$<missing>')
+      expect(s.toString()).toBe('1 = x')
+    })
+
+    it('throws on unsupported patterns instead of aborting', () => {
+      const s = new RustMagicString('ab')
+      expect(() => s.replace(/(?<=a+)b/, 'x')).toThrow()
+    })
+  })
+
+  describe('find', () => {
+    it('finds the first match in the original string', () => {
+      const s = new RustMagicString('const foo = bar(foo)')
+      s.overwrite(6, 9, 'baz')
+      expect(s.find('foo')).toEqual({
+        start: 6,
+        end: 9,
+        text: 'foo',
+        captures: []
+      })
+      expect(s.find('qux')).toBe(null)
+    })
+
+    it('finds all matches with captures', () => {
+      const s = new RustMagicString('const foo = bar(foo)')
+      const matches = s.findAll(/(f)(x)?o+/g)
+      expect(matches.map((m) => [m.start, m.end])).toEqual([
+        [6, 9],
+        [16, 19]
+      ])
+      expect(matches[0].captures[0]).toEqual({ start: 6, end: 7, text: 'f' })
+      expect(matches[0].captures[1]).toBe(null)
+    })
+
+    it('searches the generated output', () => {
+      const s = new RustMagicString('const foo = bar(foo)')
+      s.overwrite(6, 9, 'fooBaz')
+      s.appendLeft(12, 'foo')
+      const matches = s.findAll('foo', { generated: true })
+      expect(matches.map((m) => m.text)).toEqual(['foo', 'foo', 'foo'])
+      expect(matches[0].start).toBeUndefined()
+      expect(matches[1].start).toBeUndefined()
+      expect([matches[2].start, matches[2].end]).toEqual([16, 19])
+    })
+  })
+
+  describe('replace generated', () => {
+    const generated = { target: 'generated' }
+
+    it('sees content from earlier edits', () => {
+      const s = new RustMagicString('const foo = 1')
+      s.overwrite(6, 9, 'bar')
+      s.replace('bar', 'baz', generated)
+      expect(s.toString()).toBe('const baz = 1')
+    })
+
+    it('replaces across edited and inserted content', () => {
+      const s = new RustMagicString('abcdef')
+      s.overwrite(1, 2, 'X')
+      s.appendLeft(4, 'Y')
+      s.replace(/XcdY/, '[$&]', generated)
+      expect(s.toString()).toBe('a[XcdY]ef')
+    })
+
+    it('edits inserted content in place', () => {
+      const s = new RustMagicString('aXa')
+      s.prepend('a').append('a')
+      s.replaceAll('a', 'bb', generated)
+      expect(s.toString()).toBe('bbbbXbbbb')
+    })
+
+    it('handles empty matches', () => {
+      const s = new RustMagicString('ab')
+      s.appendLeft(1, 'I')
+      s.replaceAll(/x*/g, '-', generated)
+      expect(s.toString()).toBe('-a-I-b-')
+    })
+
+    it('keeps mappings of untouched text', () => {
+      const s = new RustMagicString('abcdef')
+      s.appendLeft(3, '--')
+      s.replaceAll(/c--d/g, 'Q', generated)
+      expect(s.toString()).toBe('abQef')
+      const map = new SourceMapConsumer(s.generateMap({ hires: true }))
+      expect(map.originalPositionFor({ line: 1, column: 3 })).toMatchObject({
+        line: 1,
+        column: 4
+      })
+    })
+  })
+
+  describe('replaceMany', () => {
+    it('replaces all patterns in one pass', () => {
+      const s = new RustMagicString(
+        'if (process.env.NODE_ENV && process.env.NODE_ENV_X) x = import.meta.env.DEV'
+      )
+      s.replaceMany({
+        'process.env.NODE_ENV': '"production"',
+        'process.env.NODE_ENV_X': '1',
+        'import.meta.env.DEV': 'false'
+      })
+      expect(s.toString()).toBe('if ("production" && 1) x = false')
+    })
+
+    it('prefers the longest match', () => {
+      const s = new RustMagicString('foo.barx foo.bar foobar')
+      s.replaceMany({ 'foo.bar': 'A', foo: 'B' })
+      expect(s.toString()).toBe('Ax A Bbar')
+    })
+
+    it('can require word boundaries', () => {
+      const s = new RustMagicString('foo.barx foo.bar foobar')
+      s.replaceMany({ 'foo.bar': 'A', foo: 'B' }, { wordBoundary: true })
+      expect(s.toString()).toBe('B.barx A foobar')
+    })
+  })
+
+  describe('replaceIdentifiers', () => {
+    it('only replaces whole identifiers', () => {
+      const s = new RustMagicString('let foo = foobar + $foo + [...foo]')
+      s.replaceIdentifiers({ foo: 'bar' })
+      expect(s.toString()).toBe('let bar = foobar + $foo + [...bar]')
+    })
+
+    it('skips member access and property keys', () => {
+      const s = new RustMagicString('obj.foo + obj?.foo + {foo: foo} + (a ? foo : b)')
+      s.replaceIdentifiers({ foo: 'bar' })
+      expect(s.toString()).toBe('obj.foo + obj?.foo + {foo: bar} + (a ? bar : b)')
+    })
+
+    it('can replace member access and property keys', () => {
+      const s = new RustMagicString('obj.foo + {foo: 1}')
+      s.replaceIdentifiers(
+        { foo: 'bar' },
+        { skipMemberAccess: false, skipPropertyKeys: false }
+      )
+      expect(s.toString()).toBe('obj.bar + {bar: 1}')
+    })
+
+    it('keeps the keys of shorthand properties', () => {
+      const s = new RustMagicString('const { foo } = o; f({ a, foo })')
+      s.replaceIdentifiers({ foo: 'bar' })
+      expect(s.toString()).toBe('const { foo: bar } = o; f({ a, foo: bar })')
+    })
+
+    it('only expands shorthand in object literals and patterns', () => {
+      const cases = [
+        ['`${foo}`', '`${bar}`'],
+        ['<a>{foo}</a>', '<a>{bar}</a>'],
+        ['<a b={foo} />', '<a b={bar} />'],
+        ['<a style={{ foo }} />', '<a style={{ foo: bar }} />'],
+        ['if (x) { foo }', 'if (x) { bar }'],
+        ['switch (x) { case 1: { foo } }', 'switch (x) { case 1: { bar } }'],
+        ['x = `${ { foo } }`', 'x = `${ { foo: bar } }`'],
+        ['return { foo }', 'return { foo: bar }'],
+        ['f(a ? { foo } : [{ foo }])', 'f(a ? { foo: bar } : [{ foo: bar }])'],
+      ]
+      for (const [input, expected] of cases) {
+        const s = new RustMagicString(input)
+        s.replaceIdentifiers({ foo: 'bar' })
+        expect(s.toString()).toBe(expected)
+      }
+    })
+
+    it('keeps imported and exported names', () => {
+      const s = new RustMagicString(
+        'import { foo } from "a"; export { foo }; export { foo } from "b"'
+      )
+      s.replaceIdentifiers({ foo: 'bar' })
+      expect(s.toString()).toBe(
+        'import { foo as bar } from "a"; export { bar as foo }; export { foo } from "b"'
+      )
+    })
+
+    it('accepts custom identifier characters', () => {
+      const s = new RustMagicString('a-b a-bc')
+      s.replaceIdentifiers({ 'a-b': 'z' }, { identifierChars: '[a-z-]' })
+      expect(s.toString()).toBe('z a-bc')
+    })
+
+    it('records the original names', () => {
+      const s = new RustMagicString('let foo = b + foo')
+      s.replaceIdentifiers({ foo: 'bar', b: 'c' })
+      const map = s.generateMap({ hires: true })
+      expect(map.names).toEqual(['foo', 'b'])
+      const smc = new SourceMapConsumer(map)
+      expect(smc.originalPositionFor({ line: 1, column: 14 })).toMatchObject({
+        column: 14,
+        name: 'foo'
+      })
+    })
+  })
+
+  describe('replaceInRange', () => {
+    it('replaces the first match inside the range', () => {
+      const s = new RustMagicString('foo foo foo foo')
+      s.replaceInRange(4, -4, 'foo', 'bar')
+      expect(s.toString()).toBe('foo bar foo foo')
+    })
+
+    it('replaces all matches inside the range', () => {
+      const s = new RustMagicString('foo foo foo foo')
+      s.replaceAllInRange(4, -4, /f(o+)/g, 'b$1')
+      expect(s.toString()).toBe('foo boo boo foo')
+    })
+
+    it('ignores matches crossing the range', () => {
+      const s = new RustMagicString('foo foo')
+      s.replaceAllInRange(0, 6, 'foo', 'X')
+      expect(s.toString()).toBe('X foo')
+    })
+
+    it('sees the text after the range', () => {
+      const s = new RustMagicString('foobar foo')
+      s.replaceAllInRange(0, 3, /\bfoo\b/g, 'X')
+      s.replaceAllInRange(7, 10, /\bfoo\b/g, 'Y')
+      expect(s.toString()).toBe('foobar Y')
+    })
+
+    it('keeps the text before the range as context', () => {
+      const s = new RustMagicString('ab')
+      s.replaceAllInRange(1, 2, /^b/g, 'X')
+      expect(s.toString()).toBe('ab')
+    })
+
+    it('throws on non-global regexps for the all variant', () => {
+      const s = new RustMagicString('ab')
+      expect(() => s.replaceAllInRange(0, 2, /a/, 'x')).toThrow()
+    })
+  })
+
+  describe('backtracking regexps', () => {
+    it('supports lookbehind', () => {
+      const s = new RustMagicString('a.require(x); require(y)')
+      s.replace(/(?<!\.)\brequire\(/g, '__req(')
+      expect(s.toString()).toBe('a.require(x); __req(y)')
+    })
+
+    it('supports backreferences', () => {
+      const s = new RustMagicString(`"a" 'b' "c'`)
+      s.replaceAll(/(["'])(\w)\1/g, '<$2>')
+      expect(s.toString()).toBe(`<a> <b> "c'`)
+    })
+
+    it('throws on patterns no engine supports', () => {
+      const s = new RustMagicString('ab')
+      expect(() => s.replace(/(?<=a+)b/, 'x')).toThrow(/Regex error/)
+    })
+  })
+
+  describe('JS regexp syntax', () => {
+    it('keeps character class escapes ASCII', () => {
+      const s = new RustMagicString('١٢3 é_a')
+      expect(s.findAll(/\d|\w+/g).map((m) => m.text)).toEqual(['3', '_a'])
+    })
+
+    it('stops `.` at every line terminator', () => {
+      const s = new RustMagicString('a\rb a\u2028b')
+      s.replaceAll(/a.b/g, 'x')
+      expect(s.toString()).toBe('a\rb a\u2028b')
+      s.replaceAll(/a.b/gs, 'x')
+      expect(s.toString()).toBe('x x')
+    })
+
+    it('anchors at line starts under the multiline flag', () => {
+      const s = new RustMagicString('foo\nbar')
+      s.replaceAll(/^\w/gm, '_')
+      expect(s.toString()).toBe('_oo\n_ar')
+    })
+
+    it('supports named backreferences and escapes', () => {
+      const s = new RustMagicString(`'a' \u{1F600} [x]`)
+      s.replace(/(?<q>')a\k<q>/, 'Q')
+      s.replace(/\u{1F600}/u, 'E')
+      s.replace(/[[]x]/, 'X')
+      expect(s.toString()).toBe('Q E X')
+    })
+  })
+
+  describe('sticky regexps and lastIndex', () => {
+    it('matches sticky regexps at lastIndex only', () => {
+      const re = /a/y
+      re.lastIndex = 1
+      const s = new RustMagicString('aab a')
+      s.replace(re, 'X')
+      expect(s.toString()).toBe('aXb a')
+      expect(re.lastIndex).toBe(2)
+
+      s.replace(re, 'Y')
+      expect(s.toString()).toBe('aXb a')
+      expect(re.lastIndex).toBe(0)
+    })
+
+    it('replaces contiguous matches for global sticky regexps', () => {
+      const re = /a/gy
+      re.lastIndex = 3
+      const s = new RustMagicString('aab a')
+      s.replaceAll(re, 'X')
+      expect(s.toString()).toBe('XXb a')
+      expect(re.lastIndex).toBe(0)
+    })
+
+    it('leaves lastIndex of other regexps alone', () => {
+      const re = /a/
+      re.lastIndex = 3
+      const s = new RustMagicString('aab a')
+      s.replace(re, 'X')
+      expect(s.toString()).toBe('Xab a')
+      expect(re.lastIndex).toBe(3)
+    })
+
+    it('counts lastIndex in UTF-16 code units', () => {
+      const re = /o/y
+      re.lastIndex = 2
+      const s = new RustMagicString('é o')
+      s.replace(re, '0')
+      expect(s.toString()).toBe('é 0')
+      expect(re.lastIndex).toBe(3)
+
+      const generated = /0/y
+      generated.lastIndex = 2
+      s.replace(generated, '1', { target: 'generated' })
+      expect(s.toString()).toBe('é 1')
+      expect(generated.lastIndex).toBe(3)
+    })
+  })
+
+  describe('matchAll', () => {
+    it('returns RegExpMatchArray shaped matches', () => {
+      const str = 'a1 b22 c'
+      const re = /(?<l>[a-z])(\d+)?/gd
+      const got = new RustMagicString(str).matchAll(re)
+      const expected = [...str.matchAll(re)]
+      expect(got.length).toBe(expected.length)
+      got.forEach((m, i) => {
+        expect([...m]).toEqual([...expected[i]])
+        expect(m.index).toBe(expected[i].index)
+        expect(m.input).toBe(str)
+        expect(m.groups).toEqual({ ...expected[i].groups })
+        expect([...m.indices]).toEqual([...expected[i].indices])
+        expect(m.indices.groups).toEqual({ ...expected[i].indices.groups })
+      })
+    })
+
+    it('starts at lastIndex', () => {
+      const re = /\d/g
+      re.lastIndex = 2
+      const got = new RustMagicString('1 2 3').matchAll(re)
+      expect(got.map((m) => m.index)).toEqual([2, 4])
+      expect(got[0].groups).toBeUndefined()
+      expect(got[0].indices).toBeUndefined()
+    })
+
+    it('reports indices in UTF-16 code units', () => {
+      expect(new RustMagicString('é o o').matchAll(/o/g).map((m) => m.index)).toEqual([2, 4])
+
+      const str = 'é😀 o😀 o'
+      const re = /(?<x>o)(😀)?/dg
+      re.lastIndex = 3
+      const got = new RustMagicString(str).matchAll(re)
+      re.lastIndex = 3
+      const expected = [...str.matchAll(re)]
+      expect(got.map((m) => m.index)).toEqual(expected.map((m) => m.index))
+      got.forEach((m, i) => {
+        expect([...m.indices]).toEqual([...expected[i].indices])
+        expect(m.indices.groups).toEqual({ ...expected[i].indices.groups })
+      })
+    })
+
+    it('throws on non-global regexps', () => {
+      expect(() => new RustMagicString('a').matchAll(/a/)).toThrow()
+    })
+  })
+
+  describe('SourceMap', () => {
+    it('serializes like magic-string', () => {
+      const s = new RustMagicString('function foo(){}')
+      s.overwrite(9, 12, 'bar')
+      const map = s.generateMap({ source: 'foo.js', file: 'out.js', includeContent: true })
+      const json = {
+        version: 3,
+        file: 'out.js',
+        sources: ['foo.js'],
+        sourcesContent: ['function foo(){}'],
+        names: [],
+        mappings: 'AAAA,SAAS,GAAG'
+      }
+      expect(map.toString()).toBe(JSON.stringify(json))
+      expect(JSON.parse(JSON.stringify(map))).toEqual(json)
+      expect(map.toUrl()).toBe(
+        'data:application/json;charset=utf-8;base64,' +
+          Buffer.from(JSON.stringify(json)).toString('base64')
+      )
+    })
+
+    it('can be passed along and edited', () => {
+      const s = new RustMagicString('abc')
+      const { map } = s.generate({ source: 'a.js' })
+      map.sources = ['b.js']
+      expect(map.sources).toEqual(['b.js'])
+      expect(JSON.parse(map.toString()).sources).toEqual(['b.js'])
+      expect(new SourceMapConsumer(map).sources).toEqual(['b.js'])
+    })
+
+    it('has own properties that keep mutations', () => {
+      const s = new RustMagicString('abc')
+      s.overwrite(1, 2, 'B')
+      const map = s.generateMap({ source: 'a.js', includeContent: true })
+      map.sources.push('b.js')
+      map.names.push('n')
+      expect(JSON.parse(map.toString())).toMatchObject({ sources: ['a.js', 'b.js'], names: ['n'] })
+      expect(Object.keys(map)).toEqual([
+        'version',
+        'file',
+        'sources',
+        'sourcesContent',
+        'names',
+        'mappings'
+      ])
+      expect({ ...map }).toMatchObject({ version: 3, mappings: 'AAAA,CAAC,CAAC' })
+      map.mappings = 'AAAA'
+      expect(map.toJSON().mappings).toBe('AAAA')
+    })
+  })
+
+  describe('generateMapAsync', () => {
+    it('resolves to the map generateMap returns', async () => {
+      const s = new RustMagicString('function foo(){}\nfoo()')
+      s.overwrite(9, 12, 'bar')
+      const expected = s.generateMap({ hires: true, source: 'a.js' }).toString()
+      const map = await s.generateMapAsync({ hires: true, source: 'a.js' })
+      expect(map.toString()).toBe(expected)
+    })
+
+    it('is not affected by later edits', async () => {
+      const s = new RustMagicString('abc')
+      s.overwrite(1, 2, 'B')
+      const expected = s.generateMap({ hires: true }).mappings
+      const pending = s.generateMapAsync({ hires: true })
+      s.prepend('xyz\n')
+      expect((await pending).mappings).toBe(expected)
+    })
+  })
+
+  describe('generateAsync', () => {
+    it('resolves to what generate returns', async () => {
+      const s = new RustMagicString('abc')
+      s.overwrite(1, 2, 'B')
+      const { code, map } = await s.generateAsync({ hires: true })
+      expect(code).toBe('aBc')
+      expect(map.mappings).toBe(s.generateMap({ hires: true }).mappings)
+      expect((await new RustMagicString('abc').generateAsync()).map.mappings).toBe('AAAA')
+    })
+  })
+
+  describe('Buffer input and output', () => {
+    it('accepts UTF-8 bytes', () => {
+      expect(new RustMagicString(Buffer.from('abé')).toString()).toBe('abé')
+      expect(new RustMagicString(new TextEncoder().encode('abc')).toString()).toBe('abc')
+    })
+
+    it('throws on invalid UTF-8', () => {
+      expect(() => new RustMagicString(new Uint8Array([0x61, 0xff]))).toThrow(
+        /String from utf-8 error/
+      )
+    })
+
+    it('returns the code as a Buffer', () => {
+      const s = new RustMagicString('abc')
+      s.overwrite(1, 2, 'B')
+      const buffer = s.toBuffer()
+      expect(Buffer.isBuffer(buffer)).toBe(true)
+      expect(buffer.toString()).toBe('aBc')
+    })
+
+    it('returns the encoded mappings as bytes', () => {
+      const s = new RustMagicString('function foo(){}\nfoo()')
+      s.overwrite(9, 12, 'bar')
+      const mappings = s.generateEncodedMappings({ hires: true })
+      expect(mappings).toBeInstanceOf(Uint8Array)
+      expect(Buffer.from(mappings).toString()).toBe(s.generateMap({ hires: true }).mappings)
+    })
+
+    it('encodes the same mappings as generateMap after a cached generation', () => {
+      const s = new RustMagicString('abc\ndef')
+      s.overwrite(1, 2, 'X')
+      const map = s.generateMap({ hires: true, source: 'a.js', includeContent: true })
+      expect(Buffer.from(s.generateEncodedMappings({ hires: true })).toString()).toBe(map.mappings)
+      expect(Buffer.from(s.generateEncodedMappings()).toString()).toBe(s.generateMap().mappings)
+      expect(s.toBuffer().toString()).toBe(s.toString())
+    })
+  })
+
+  describe('warnings', () => {
+    it('collects warnings instead of emitting them', () => {
+      const s = new RustMagicString('abc', { collectWarnings: true })
+      s.insertLeft(1, 'x')
+      s.indent('  ', { exclude: [[0]] })
+      expect(s.takeWarnings().map(w => w.code)).toEqual([
+        'FMS_DEPRECATED_API',
+        'FMS_IGNORED_OPTION',
+      ])
+      expect(s.takeWarnings()).toEqual([])
+    })
+
+    it('emits each warning once through process.emitWarning', async () => {
+      const warnings = []
+      const onWarning = w => warnings.push(w)
+      process.on('warning', onWarning)
+      new RustMagicString('abc').insertRight(1, 'x')
+      new RustMagicString('abc').insertRight(2, 'y')
+      await new Promise(resolve => setImmediate(resolve))
+      process.off('warning', onWarning)
+      const emitted = warnings.filter(w => w.message.includes('insertRight'))
+      expect(emitted).toHaveLength(1)
+      expect(emitted[0].name).toBe('DeprecationWarning')
+      expect(emitted[0].code).toBe('FMS_DEPRECATED_API')
+    })
+
+    it('emits a warning once even if its message quotes the arguments', async () => {
+      const warnings = []
+      const onWarning = w => warnings.push(w)
+      process.on('warning', onWarning)
+      new RustMagicString('a\nb').indent('  ', { exclude: [[0]] })
+      new RustMagicString('a\nb').indent('  ', { exclude: [[1]] })
+      await new Promise(resolve => setImmediate(resolve))
+      process.off('warning', onWarning)
+      expect(warnings.filter(w => w.message.startsWith('indent'))).toHaveLength(1)
+    })
+
+    it('warns about malformed dedent and reindent exclusion ranges', () => {
+      const s = new RustMagicString('  a\n  b', { collectWarnings: true })
+      s.dedent(undefined, { exclude: [[0, 1, 2]] })
+      expect(s.toString()).toBe('a\nb')
+      expect(s.takeWarnings().map(w => w.code)).toEqual(['FMS_IGNORED_OPTION'])
+    })
+
+    it('keeps collected warnings across async generation', async () => {
+      const s = new RustMagicString('abc', { collectWarnings: true })
+      s.insertLeft(1, 'x')
+      const map = await s.generateMapAsync({ hires: true })
+      expect(map.mappings).toBe(s.generateMap({ hires: true }).mappings)
+      expect(s.takeWarnings().map(w => w.code)).toEqual(['FMS_DEPRECATED_API'])
+    })
+
+    it('warns about sourcemap locations lost to an edit', async () => {
+      const s = new RustMagicString('abcdef', { collectWarnings: true })
+      s.addSourcemapLocation(1)
+      s.overwrite(0, 3, 'X')
+      await s.generateMapAsync()
+      s.generate()
+      expect(s.takeWarnings().map(w => w.code)).toEqual([
+        'FMS_LOSSY_MAPPING',
+        'FMS_LOSSY_MAPPING',
+      ])
+    })
+  })
+
+  describe('introspection', () => {
+    it('exposes the original string', () => {
+      const s = new RustMagicString('abc')
+      s.overwrite(0, 1, 'A')
+      expect(s.original).toBe('abc')
+    })
+
+    it('reads the end of the generated string', () => {
+      const s = new RustMagicString('abc\ndef')
+      expect(s.length()).toBe(7)
+      expect(s.lastChar()).toBe('f')
+      expect(s.lastLine()).toBe('def')
+      s.remove(4, 7)
+      expect(s.lastChar()).toBe('\n')
+      expect(s.lastLine()).toBe('')
+      s.appendLeft(7, 'gh').append('i')
+      expect(s.lastLine()).toBe('ghi')
+      expect(s.length()).toBe(s.toString().length)
+    })
+
+    it('counts the length in UTF-16 code units', () => {
+      const s = new RustMagicString('é😀')
+      expect(s.length()).toBe(3)
+      s.append('ü')
+      expect(s.length()).toBe(s.toString().length)
+      s.toString()
+      expect(s.length()).toBe(4)
+    })
+
+    it('handles an empty string', () => {
+      const s = new RustMagicString('')
+      expect(s.lastChar()).toBe('')
+      expect(s.lastLine()).toBe('')
+      s.prepend('a\nb')
+      expect(s.lastLine()).toBe('b')
+    })
+
+    it('returns the guessed indent string', () => {
+      expect(new RustMagicString('a\n  b\n  c').getIndentString()).toBe('  ')
+      expect(new RustMagicString('a\n\tb').getIndentString()).toBe('\t')
+      expect(new RustMagicString('a').getIndentString()).toBe('\t')
+    })
+
+    it('shifts indices by offset', () => {
+      const s = new RustMagicString('foo bar baz', { offset: 4 })
+      expect(s.offset).toBe(4)
+      s.overwrite(0, 3, 'BAR')
+      expect(s.toString()).toBe('foo BAR baz')
+      expect(s.slice()).toBe('BAR baz')
+      expect(s.snip(0, 3).toString()).toBe('BAR')
+      s.offset = 0
+      s.overwrite(0, 3, 'FOO')
+      expect(s.toString()).toBe('FOO BAR baz')
+      expect(s.clone().offset).toBe(0)
+    })
+
+    it('replaces at the match positions when offset is set', () => {
+      const s = new RustMagicString('xxabcabc', { offset: 2 })
+      s.replace('abc', 'X')
+      expect(s.toString()).toBe('xxXabc')
+      const t = new RustMagicString('xxabcabc', { offset: 2 })
+      t.replace(/abc/, 'X')
+      expect(t.toString()).toBe('xxXabc')
+      const u = new RustMagicString('xxabcabc', { offset: 2 })
+      u.replaceAll(/b(c)/g, '[$1]')
+      expect(u.toString()).toBe('xxa[c]a[c]')
+    })
+
+    it('throws when offset pushes an index out of range', () => {
+      const s = new RustMagicString('abc', { offset: 0xffffffff })
+      expect(() => s.appendLeft(1, 'x')).toThrow(/Index out of range/)
+      expect(() => s.overwrite(0, 1, 'x')).toThrow(/Index out of range/)
+      expect(s.toString()).toBe('abc')
+    })
+  })
 })
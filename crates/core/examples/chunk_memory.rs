@@ -0,0 +1,57 @@
+// Heap held by a `MagicString` over a 5 MB source after splitting it into many chunks.
+//
+//   cargo run --release -p fast_magic_string --example chunk_memory
+
+use std::{
+  alloc::{GlobalAlloc, Layout, System},
+  sync::atomic::{AtomicUsize, Ordering},
+};
+
+use fast_magic_string::MagicString;
+
+struct Counting;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    let live = LIVE.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+    PEAK.fetch_max(live, Ordering::Relaxed);
+    System.alloc(layout)
+  }
+
+  unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+    System.dealloc(ptr, layout)
+  }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const SIZE: usize = 5 * 1024 * 1024;
+
+fn mib(bytes: usize) -> f64 {
+  bytes as f64 / (1024.0 * 1024.0)
+}
+
+fn main() {
+  let source = "const answer = 42;\n".repeat(SIZE / 19);
+  for splits in [1_000, 10_000, 100_000] {
+    let base = LIVE.load(Ordering::Relaxed);
+    PEAK.store(base, Ordering::Relaxed);
+    let mut s = MagicString::new(&source, None);
+    let step = source.len() / splits;
+    for i in 1..splits {
+      s.append_left((i * step) as u32, "").unwrap();
+    }
+    println!(
+      "{:>7} splits: {:>7.2} MiB live, {:>7.2} MiB peak",
+      splits,
+      mib(LIVE.load(Ordering::Relaxed) - base),
+      mib(PEAK.load(Ordering::Relaxed) - base),
+    );
+    drop(s);
+  }
+}
@@ -1,6 +1,6 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, rc::Rc, sync::Arc};
 
-use regex::Regex;
+use fms_regexp::CompiledRegex;
 
 use crate::error::{Error, FmsErrType};
 use crate::result::Result;
@@ -9,25 +9,39 @@ use crate::result::Result;
 pub struct Chunk {
   pub start: u32,
   pub end: u32,
-  pub original: String,
+  // the whole original string, shared by every chunk
+  source: Arc<str>,
   pub intro: String,
   pub outro: String,
-  pub content: String,
+  // `None` until the chunk is edited, the content is `source[start..end]` then
+  content: Option<String>,
   pub store_name: bool,
   pub edited: bool,
   pub previous: Option<Rc<RefCell<Chunk>>>,
   pub next: Option<Rc<RefCell<Chunk>>>,
 }
 
+// a chunk without its links and shared source, see `MagicStringSnapshot`
+#[derive(Debug, Clone)]
+pub struct ChunkSnapshot {
+  pub start: u32,
+  pub end: u32,
+  pub intro: String,
+  pub outro: String,
+  content: Option<String>,
+  pub store_name: bool,
+  pub edited: bool,
+}
+
 impl Chunk {
-  pub fn new(start: u32, end: u32, content: &str) -> Self {
+  pub fn new(source: Arc<str>, start: u32, end: u32) -> Self {
     Chunk {
       start,
       end,
-      original: content.to_string(),
+      source,
       intro: String::default(),
       outro: String::default(),
-      content: content.to_string(),
+      content: None,
       store_name: false,
       edited: false,
       previous: None,
@@ -35,20 +49,50 @@ impl Chunk {
     }
   }
 
+  pub fn original(&self) -> &str {
+    &self.source[self.start as usize..self.end as usize]
+  }
+
+  pub fn content(&self) -> &str {
+    self.content.as_deref().unwrap_or_else(|| self.original())
+  }
+
   pub fn is_edited(&self) -> bool {
-    self.edited || (self.original.len() != self.content.len()) || self.original != self.content
+    self.edited || self.content.as_ref().is_some_and(|c| c != self.original())
   }
 
   pub fn self_clone(&self) -> Chunk {
-    let mut cloned = Chunk::new(self.start, self.end, self.original.as_str());
+    let mut cloned = Chunk::new(Arc::clone(&self.source), self.start, self.end);
     cloned.intro = self.intro.clone();
-    cloned.intro = self.outro.clone();
+    cloned.outro = self.outro.clone();
     cloned.content = self.content.clone();
     cloned.store_name = self.store_name;
     cloned.edited = self.edited;
     cloned
   }
 
+  pub fn snapshot(&self) -> ChunkSnapshot {
+    ChunkSnapshot {
+      start: self.start,
+      end: self.end,
+      intro: self.intro.clone(),
+      outro: self.outro.clone(),
+      content: self.content.clone(),
+      store_name: self.store_name,
+      edited: self.edited,
+    }
+  }
+
+  pub fn from_snapshot(source: Arc<str>, snapshot: ChunkSnapshot) -> Chunk {
+    let mut chunk = Chunk::new(source, snapshot.start, snapshot.end);
+    chunk.intro = snapshot.intro;
+    chunk.outro = snapshot.outro;
+    chunk.content = snapshot.content;
+    chunk.store_name = snapshot.store_name;
+    chunk.edited = snapshot.edited;
+    chunk
+  }
+
   pub fn append_left(&mut self, str: &str) -> &Self {
     self.outro = concat_string!(self.outro, str);
     self
@@ -78,13 +122,13 @@ impl Chunk {
     self.outro = String::default();
     if self.edited {
       self.edited = false;
-      self.content = self.original.clone();
+      self.content = None;
       self.store_name = false;
     }
   }
 
   pub fn edit(&mut self, content: &str, store_name: bool, content_only: bool) -> &Self {
-    self.content = content.to_string();
+    self.content = Some(content.to_string());
     if !content_only {
       self.intro.clear();
       self.outro.clear();
@@ -94,14 +138,14 @@ impl Chunk {
     self
   }
 
-  pub fn trim_start(&mut self, reg: &Regex) -> bool {
-    self.intro = reg.replace(&self.intro, "").to_string();
+  pub fn trim_start(&mut self, reg: &CompiledRegex) -> Result<bool> {
+    self.intro = reg.replace(&self.intro, "")?;
     if !self.intro.is_empty() {
-      return true;
+      return Ok(true);
     }
-    let trimmed = reg.replace(&self.content, "").to_string();
+    let trimmed = reg.replace(self.content(), "")?;
     if !trimmed.is_empty() {
-      if trimmed != self.content {
+      if trimmed != self.content() {
         // TODO: aligned with magic-string
         // let new_chunk = Chunk::split(
         //   Rc::new(RefCell::new(self.clone())),
@@ -113,25 +157,25 @@ impl Chunk {
         // }
         self.edit(trimmed.as_str(), self.store_name, true);
       }
-      return true;
+      return Ok(true);
     } else {
       self.edit("", self.store_name, true);
-      self.outro = reg.replace(&self.outro, "").to_string();
+      self.outro = reg.replace(&self.outro, "")?;
       if !self.outro.is_empty() {
-        return true;
+        return Ok(true);
       }
     }
-    false
+    Ok(false)
   }
 
-  pub fn trim_end(&mut self, reg: &Regex) -> bool {
-    self.outro = reg.replace(&self.outro, "").to_string();
+  pub fn trim_end(&mut self, reg: &CompiledRegex) -> Result<bool> {
+    self.outro = reg.replace(&self.outro, "")?;
     if !self.outro.is_empty() {
-      return true;
+      return Ok(true);
     }
-    let trimmed = reg.replace(&self.content, "").to_string();
+    let trimmed = reg.replace(self.content(), "")?;
     if !trimmed.is_empty() {
-      if trimmed != self.content {
+      if trimmed != self.content() {
         // TODO: aligned with magic-string
         // let new_chunk = Chunk::split(
         //   Rc::new(RefCell::new(self.clone())),
@@ -143,15 +187,15 @@ impl Chunk {
         // }
         self.edit(trimmed.as_str(), self.store_name, true);
       }
-      return true;
+      return Ok(true);
     } else {
       self.edit("", self.store_name, true);
-      self.intro = reg.replace(&self.intro, "").to_string();
+      self.intro = reg.replace(&self.intro, "")?;
       if !self.intro.is_empty() {
-        return true;
+        return Ok(true);
       }
     }
-    false
+    Ok(false)
   }
 
   pub fn split(chunk: Rc<RefCell<Chunk>>, index: u32) -> Result<Rc<RefCell<Chunk>>> {
@@ -164,31 +208,19 @@ impl Chunk {
       ));
     }
 
-    // split str
-    let mid_index = (index - cur_chunk.start) as usize;
-    let origin_before = cur_chunk.original[0..mid_index].to_owned();
-    let origin_after = cur_chunk.original[mid_index..].to_owned();
-
     // create new chunk
     let new_chunk: Rc<RefCell<Chunk>> = Rc::new(RefCell::new(Chunk::new(
+      Arc::clone(&cur_chunk.source),
       index,
       cur_chunk.end,
-      &origin_after,
     )));
     new_chunk.borrow_mut().outro = cur_chunk.outro.to_owned();
-    new_chunk.borrow_mut().next = {
-      if cur_chunk.next.is_some() {
-        Some(Rc::clone(cur_chunk.next.as_ref().unwrap()))
-      } else {
-        None
-      }
-    };
+    new_chunk.borrow_mut().next = cur_chunk.next.as_ref().map(Rc::clone);
 
     new_chunk.borrow_mut().previous = Some(Rc::clone(&chunk));
 
     // update current chunk
-    cur_chunk.original = origin_before.to_string();
-    cur_chunk.content = cur_chunk.original.clone();
+    cur_chunk.content = None;
     cur_chunk.end = index;
     cur_chunk.outro.clear();
     if cur_chunk.next.is_some() {
@@ -203,7 +235,7 @@ impl Chunk {
     // ```
     if cur_chunk.is_edited() {
       new_chunk.borrow_mut().edit("", false, false);
-      cur_chunk.content = "".to_string();
+      cur_chunk.content = Some(String::default());
     }
     cur_chunk.next = Some(Rc::clone(&new_chunk));
 
@@ -248,3 +280,152 @@ impl Chunk {
     Ok(())
   }
 }
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ChunkView {
+  pub start: u32,
+  pub end: u32,
+  pub original: String,
+  pub intro: String,
+  pub content: String,
+  pub outro: String,
+  pub edited: bool,
+  pub store_name: bool,
+  // offset of `intro` in the generated string
+  pub generated_offset: u32,
+}
+
+pub struct Chunks {
+  cur: Option<Rc<RefCell<Chunk>>>,
+  generated_offset: u32,
+}
+
+impl Chunks {
+  pub fn new(first_chunk: Rc<RefCell<Chunk>>, generated_offset: u32) -> Self {
+    Self {
+      cur: Some(first_chunk),
+      generated_offset,
+    }
+  }
+}
+
+impl Iterator for Chunks {
+  type Item = ChunkView;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let cur = self.cur.take()?;
+    let chunk = cur.borrow();
+    let view = ChunkView {
+      start: chunk.start,
+      end: chunk.end,
+      original: chunk.original().to_string(),
+      intro: chunk.intro.clone(),
+      content: chunk.content().to_string(),
+      outro: chunk.outro.clone(),
+      edited: chunk.is_edited(),
+      store_name: chunk.store_name,
+      generated_offset: self.generated_offset,
+    };
+    self.generated_offset += (chunk.intro.len() + chunk.content().len() + chunk.outro.len()) as u32;
+    self.cur = chunk.next.as_ref().map(Rc::clone);
+    Some(view)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::ChunkView;
+  use crate::{MagicString, OverwriteOptions};
+
+  fn view(start: u32, end: u32, original: &str, generated_offset: u32) -> ChunkView {
+    ChunkView {
+      start,
+      end,
+      original: original.to_string(),
+      intro: String::new(),
+      content: original.to_string(),
+      outro: String::new(),
+      edited: false,
+      store_name: false,
+      generated_offset,
+    }
+  }
+
+  #[test]
+  fn yields_a_single_chunk_for_an_unedited_string() {
+    let s = MagicString::new("abc", None);
+    assert_eq!(s.chunks().collect::<Vec<_>>(), vec![view(0, 3, "abc", 0)]);
+  }
+
+  #[test]
+  fn offsets_chunks_by_everything_rendered_before() {
+    let mut s = MagicString::new("abcdef", None);
+    s.prepend(">>").unwrap();
+    s.append_left(2, "_").unwrap();
+    s.prepend_right(2, "[").unwrap();
+    s.remove(4, 6).unwrap();
+
+    let chunks: Vec<_> = s.chunks().collect();
+    assert_eq!(
+      chunks,
+      vec![
+        ChunkView {
+          outro: "_".to_string(),
+          ..view(0, 2, "ab", 2)
+        },
+        ChunkView {
+          intro: "[".to_string(),
+          ..view(2, 4, "cd", 5)
+        },
+        ChunkView {
+          content: String::new(),
+          edited: true,
+          ..view(4, 6, "ef", 8)
+        },
+      ]
+    );
+    assert_eq!(s.to_string(), ">>ab_[cd");
+  }
+
+  #[test]
+  fn yields_moved_chunks_in_generated_order() {
+    let mut s = MagicString::new("abcdef", None);
+    s._move(0, 2, 6).unwrap();
+    let ranges: Vec<_> = s
+      .chunks()
+      .map(|c| (c.start, c.end, c.generated_offset))
+      .collect();
+    assert_eq!(ranges, vec![(2, 6, 0), (0, 2, 4)]);
+  }
+
+  #[test]
+  fn flags_overwritten_chunks() {
+    let mut s = MagicString::new("let a = 1", None);
+    s.overwrite(
+      4,
+      5,
+      "b",
+      Some(OverwriteOptions {
+        store_name: Some(true),
+        ..Default::default()
+      }),
+    )
+    .unwrap();
+    // rewriting a chunk to its own text is still an edit
+    s.overwrite(8, 9, "1", None).unwrap();
+
+    let flags: Vec<_> = s
+      .chunks()
+      .map(|c| (c.content, c.edited, c.store_name))
+      .collect();
+    assert_eq!(
+      flags,
+      vec![
+        ("let ".to_string(), false, false),
+        ("b".to_string(), true, true),
+        (" = ".to_string(), false, false),
+        ("1".to_string(), true, false),
+      ]
+    );
+  }
+}
@@ -1,5 +1,6 @@
-use std::{io, string};
+use std::{fmt, io, str, string};
 
+use fms_regexp::RegexError;
 use fms_sourcemap::error::SourcemapError;
 
 #[derive(Debug)]
@@ -31,6 +32,20 @@ impl From<io::Error> for Error {
   }
 }
 
+impl From<RegexError> for Error {
+  #[inline]
+  fn from(err: RegexError) -> Self {
+    Error::from_reason(FmsErrType::Regex, err.message.as_str())
+  }
+}
+
+impl From<fmt::Error> for Error {
+  #[inline]
+  fn from(_: fmt::Error) -> Self {
+    Error::new(FmsErrType::IO)
+  }
+}
+
 impl From<SourcemapError> for Error {
   #[inline]
   fn from(err: SourcemapError) -> Self {
@@ -49,6 +64,13 @@ impl From<string::FromUtf8Error> for Error {
   }
 }
 
+impl From<str::Utf8Error> for Error {
+  #[inline]
+  fn from(err: str::Utf8Error) -> Self {
+    Error::from_reason(FmsErrType::StringFromUTF8, err.to_string().as_str())
+  }
+}
+
 impl Error {
   pub fn new(err_type: FmsErrType) -> Self {
     Self {
@@ -77,4 +99,5 @@ pub enum FmsErrType {
   Vlq,
   StringFromUTF8,
   Slice,
+  Regex,
 }
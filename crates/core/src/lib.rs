@@ -1,32 +1,49 @@
-#[deny(clippy::inherent_to_string)]
 pub mod error;
 pub mod locator;
+pub mod position;
+pub mod regex_cache;
+pub mod replacement;
 pub mod result;
+pub mod search;
+pub mod snapshot;
 pub mod utils;
+pub mod warning;
 
 use crate::{
   error::{Error, FmsErrType},
   fms_sourcemap::{
     bit_set::BitSet,
-    mappings::{MappingsFacade, SOURCE_INDEX},
+    mappings::{encode_mappings, Mappings, MappingsEncoder, MappingsFacade, SOURCE_INDEX},
     DecodedMap, SourceMap, SOURCEMAP_VERSION,
   },
   locator::Locator,
+  position::{MappedPosition, PositionIndex},
+  regex_cache::cached_regex,
+  replacement::ReplacementTemplate,
   result::Result,
-  utils::{guess_indent, match_all, normalize_range, slice_string},
+  search::{
+    find_identifiers, find_many, find_words, shorthand, FindMatch, FindOptions, IdentifierOptions,
+    MatchAll, Pattern, RegexState, ReplaceManyOptions, ReplaceTarget, Shorthand,
+  },
+  snapshot::MagicStringSnapshot,
+  utils::{
+    apply_line_ending_edits, dedent_char, dedent_str, guess_indent, indent_units, is_indent_char,
+    line_ending_edits, match_all, match_all_in, min_indent, normalize_range, reindent_str,
+    slice_string, utf16_to_byte_index,
+  },
+  warning::{Warning, WarningCode},
 };
 
 #[macro_use(concat_string)]
 extern crate fms_utils;
 
-use std::{cell::RefCell, collections::HashMap, rc::Rc, str, vec};
+use std::{cell::RefCell, collections::HashMap, fmt, io, rc::Rc, str, sync::Arc, vec};
 
 pub use fms_sourcemap;
 
 pub mod chunk;
-use chunk::Chunk;
+use chunk::{Chunk, Chunks};
 use fms_utils::path::get_relative_path;
-use regex::{Captures, Regex};
 
 #[derive(Clone)]
 pub struct GenerateMapOptions {
@@ -77,6 +94,8 @@ pub struct MagicStringOptions {
   pub filename: Option<String>,
   pub indent_exclusion_ranges: Option<IndentExclusionRanges>,
   pub ignore_list: Option<bool>,
+  pub one_based_lines: Option<bool>,
+  pub offset: Option<u32>,
 }
 
 impl Default for MagicStringOptions {
@@ -85,77 +104,203 @@ impl Default for MagicStringOptions {
       filename: Some(String::default()),
       indent_exclusion_ranges: None,
       ignore_list: Some(false),
+      one_based_lines: Some(false),
+      offset: None,
+    }
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+  Lf,
+  CrLf,
+}
+
+impl LineEnding {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      LineEnding::Lf => "\n",
+      LineEnding::CrLf => "\r\n",
+    }
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum IndentStyle {
+  Tab,
+  Spaces(u32),
+}
+
+impl IndentStyle {
+  // inverse of `as_string` for the output of `guess_indent`
+  pub fn from_indent_str(indent_str: &str) -> Self {
+    if indent_str.starts_with('\t') {
+      IndentStyle::Tab
+    } else {
+      IndentStyle::Spaces(indent_str.len() as u32)
+    }
+  }
+
+  pub fn as_string(&self) -> String {
+    match self {
+      IndentStyle::Tab => "\t".to_string(),
+      IndentStyle::Spaces(width) => " ".repeat(*width as usize),
     }
   }
 }
 
+#[derive(Default)]
+pub struct ReindentOptions {
+  pub start: Option<i32>,
+  pub end: Option<i32>,
+  pub exclude: Option<Vec<Vec<u32>>>,
+}
+
 #[derive(Default)]
 pub struct IndentOptions {
   pub exclude: Option<Vec<Vec<u32>>>,
   pub indent_start: Option<bool>,
 }
 
+// a piece of the rendered output
+enum Slot {
+  Intro,
+  Outro,
+  ChunkIntro(Rc<RefCell<Chunk>>),
+  // original content of an unedited chunk or the content of an edited one
+  ChunkContent(Rc<RefCell<Chunk>>),
+  ChunkOutro(Rc<RefCell<Chunk>>),
+}
+
+#[derive(Default)]
+struct RenderCache {
+  code: Option<String>,
+  changed: Option<bool>,
+  // keyed by `hires`
+  mappings: Option<(bool, Mappings)>,
+}
+
 #[allow(non_camel_case_types)]
 pub struct MagicString {
-  pub original: String,
-  pub intro: String,
-  pub outro: String,
-  pub start_index_chunk_map: HashMap<u32, Rc<RefCell<Chunk>>>,
-  pub end_index_chunk_map: HashMap<u32, Rc<RefCell<Chunk>>>,
+  pub original: Arc<str>,
+  // private so every edit goes through `_invalidate`
+  intro: String,
+  outro: String,
+  start_index_chunk_map: HashMap<u32, Rc<RefCell<Chunk>>>,
+  end_index_chunk_map: HashMap<u32, Rc<RefCell<Chunk>>>,
 
   last_searched_chunk: Rc<RefCell<Chunk>>,
   first_chunk: Rc<RefCell<Chunk>>,
   last_chunk: Rc<RefCell<Chunk>>,
   stored_names: Vec<String>,
+  // position of each stored name in `stored_names`
+  stored_name_indices: HashMap<String, usize>,
   ignore_list: bool,
   sourcemap_locations: BitSet,
   indent_str: Option<String>,
+  offset: u32,
+  position_index: RefCell<Option<Rc<PositionIndex>>>,
+  render_cache: RefCell<RenderCache>,
+  warnings: RefCell<Vec<Warning>>,
+  edit_count: u64,
   _locator: Locator,
   _raw_options: MagicStringOptions,
 }
 
 impl MagicString {
   pub fn new(str: &str, options: Option<MagicStringOptions>) -> Self {
+    Self::_with_source(Arc::from(str), options)
+  }
+
+  // `original` is shared with the caller, e.g. by clones of the same string
+  fn _with_source(original: Arc<str>, options: Option<MagicStringOptions>) -> Self {
+    let len: u32 = original.len().try_into().unwrap();
+    let chunk = Rc::new(RefCell::new(Chunk::new(Arc::clone(&original), 0, len)));
+    Self::_with_chunks(original, vec![chunk], options)
+  }
+
+  // `chunks` are in order, cover `original` and must not be empty
+  fn _with_chunks(
+    original: Arc<str>,
+    chunks: Vec<Rc<RefCell<Chunk>>>,
+    options: Option<MagicStringOptions>,
+  ) -> Self {
     let options = options.unwrap_or_default();
-    let chunk = Rc::new(RefCell::new(Chunk::new(
-      0,
-      str.len().try_into().unwrap(),
-      str,
-    )));
-    let init_start_index_chunk_map: Vec<(u32, Rc<RefCell<Chunk>>)> = vec![(0, chunk.clone())];
-    let init_end_index_chunk_map: Vec<(u32, Rc<RefCell<Chunk>>)> =
-      vec![(str.len().try_into().unwrap(), chunk.clone())];
+    let locator = Locator::new(&original);
+    let mut start_index_chunk_map = HashMap::with_capacity(chunks.len());
+    let mut end_index_chunk_map = HashMap::with_capacity(chunks.len());
+    for (i, chunk) in chunks.iter().enumerate() {
+      if let Some(next) = chunks.get(i + 1) {
+        chunk.borrow_mut().next = Some(Rc::clone(next));
+        next.borrow_mut().previous = Some(Rc::clone(chunk));
+      }
+      let (start, end) = (chunk.borrow().start, chunk.borrow().end);
+      start_index_chunk_map.insert(start, Rc::clone(chunk));
+      end_index_chunk_map.insert(end, Rc::clone(chunk));
+    }
+    let first_chunk = Rc::clone(chunks.first().unwrap());
+    let last_chunk = Rc::clone(chunks.last().unwrap());
     Self {
-      original: str.to_string(),
+      original,
       intro: String::default(),
       outro: String::default(),
-      start_index_chunk_map: init_start_index_chunk_map.into_iter().collect(),
-      end_index_chunk_map: init_end_index_chunk_map.into_iter().collect(),
+      start_index_chunk_map,
+      end_index_chunk_map,
 
-      last_searched_chunk: Rc::clone(&chunk),
-      first_chunk: Rc::clone(&chunk),
-      last_chunk: Rc::clone(&chunk),
+      last_searched_chunk: Rc::clone(&first_chunk),
+      first_chunk,
+      last_chunk,
       stored_names: vec![],
+      stored_name_indices: HashMap::new(),
       ignore_list: options.ignore_list.unwrap_or_default(),
       sourcemap_locations: BitSet::new(None),
       indent_str: None,
-      _locator: Locator::new(str),
+      offset: options.offset.unwrap_or_default(),
+      position_index: RefCell::new(None),
+      render_cache: RefCell::new(RenderCache::default()),
+      warnings: RefCell::new(vec![]),
+      edit_count: 0,
+      _locator: locator,
       _raw_options: options,
     }
   }
 
+  // `bytes` must be valid UTF-8
+  pub fn from_bytes(bytes: &[u8], options: Option<MagicStringOptions>) -> Result<Self> {
+    Ok(Self::new(str::from_utf8(bytes)?, options))
+  }
+
+  // warnings raised since the last call, oldest first
+  pub fn take_warnings(&self) -> Vec<Warning> {
+    self.warnings.take()
+  }
+
+  fn _warn(&self, code: WarningCode, api: &'static str, message: &str) {
+    self
+      .warnings
+      .borrow_mut()
+      .push(Warning::new(code, api, message));
+  }
+
   pub fn add_sourcemap_location(&mut self, index: u32) -> &Self {
+    self.render_cache.get_mut().mappings.take();
     self.sourcemap_locations.add(index as usize);
     self
   }
 
   pub fn append(&mut self, str: &str) -> Result<&mut Self> {
+    self._invalidate();
     self.outro = concat_string!(self.outro, str);
     Ok(self)
   }
 
   pub fn append_left(&mut self, index: u32, content: &str) -> Result<&mut Self> {
+    self._append_left(self._offset_index(index)?, content)
+  }
+
+  fn _append_left(&mut self, index: u32, content: &str) -> Result<&mut Self> {
     self._split(index)?;
+    self._invalidate();
     if let Some(chunk) = self.end_index_chunk_map.get(&index) {
       let mut chunk = chunk.borrow_mut();
       chunk.append_left(content);
@@ -165,8 +310,28 @@ impl MagicString {
     Ok(self)
   }
 
+  pub fn insert_left(&mut self, index: u32, content: &str) -> Result<&mut Self> {
+    self._warn(
+      WarningCode::DeprecatedApi,
+      "insertLeft",
+      "magicString.insertLeft(...) is deprecated. Use magicString.appendLeft(...) instead",
+    );
+    self.append_left(index, content)
+  }
+
+  pub fn insert_right(&mut self, index: u32, content: &str) -> Result<&mut Self> {
+    self._warn(
+      WarningCode::DeprecatedApi,
+      "insertRight",
+      "magicString.insertRight(...) is deprecated. Use magicString.prependRight(...) instead",
+    );
+    self.prepend_right(index, content)
+  }
+
   pub fn append_right(&mut self, index: u32, content: &str) -> Result<&mut Self> {
+    let index = self._offset_index(index)?;
     self._split(index)?;
+    self._invalidate();
     if let Some(chunk) = self.start_index_chunk_map.get(&index) {
       let mut chunk: std::cell::RefMut<'_, Chunk> = chunk.borrow_mut();
       chunk.append_right(content);
@@ -176,8 +341,53 @@ impl MagicString {
     Ok(self)
   }
 
+  pub fn snapshot(&self) -> MagicStringSnapshot {
+    let mut chunks = vec![];
+    let mut chunk = Some(Rc::clone(&self.first_chunk));
+    while let Some(c) = chunk {
+      chunks.push(c.borrow().snapshot());
+      chunk = c.borrow().next.clone();
+    }
+    MagicStringSnapshot {
+      original: Arc::clone(&self.original),
+      intro: self.intro.clone(),
+      outro: self.outro.clone(),
+      chunks,
+      stored_names: self.stored_names.clone(),
+      sourcemap_locations: self.sourcemap_locations.clone(),
+      indent_str: self.indent_str.clone(),
+      edit_count: self.edit_count,
+      options: self._raw_options.clone(),
+    }
+  }
+
+  pub fn from_snapshot(snapshot: MagicStringSnapshot) -> MagicString {
+    let chunks = snapshot
+      .chunks
+      .into_iter()
+      .map(|chunk| {
+        Rc::new(RefCell::new(Chunk::from_snapshot(
+          Arc::clone(&snapshot.original),
+          chunk,
+        )))
+      })
+      .collect();
+    let mut restored = MagicString::_with_chunks(snapshot.original, chunks, Some(snapshot.options));
+
+    restored.intro = snapshot.intro;
+    restored.outro = snapshot.outro;
+    for name in snapshot.stored_names {
+      restored._store_name(name);
+    }
+    restored.sourcemap_locations = snapshot.sourcemap_locations;
+    restored.indent_str = snapshot.indent_str;
+    restored.edit_count = snapshot.edit_count;
+    restored
+  }
+
   pub fn _clone(&self) -> MagicString {
-    let mut cloned = MagicString::new(self.original.as_str(), Some(self._raw_options.clone()));
+    let mut cloned =
+      MagicString::_with_source(Arc::clone(&self.original), Some(self._raw_options.clone()));
     cloned.first_chunk = Rc::new(RefCell::new(self.first_chunk.borrow().self_clone()));
     cloned.last_chunk = Rc::clone(&cloned.first_chunk);
     cloned.last_searched_chunk = Rc::clone(&cloned.first_chunk);
@@ -197,13 +407,11 @@ impl MagicString {
 
         // update `next cloned chunk`
         let original_next = o.borrow().next.clone();
-        let cloned_next = if original_next.is_some() {
-          let mut cloned_next = original_next.unwrap().borrow().self_clone();
+        let cloned_next = original_next.map(|original_next| {
+          let mut cloned_next = original_next.borrow().self_clone();
           cloned_next.previous = Some(c.clone());
-          Some(Rc::new(RefCell::new(cloned_next)))
-        } else {
-          None
-        };
+          Rc::new(RefCell::new(cloned_next))
+        });
 
         // connect `cloned chunk` and `next cloned chunk`
         cloned_chunk = if cloned_next.is_some() {
@@ -220,6 +428,8 @@ impl MagicString {
     cloned.intro = self.intro.clone();
     cloned.outro = self.outro.clone();
     cloned.sourcemap_locations = self.sourcemap_locations.clone();
+    cloned.edit_count = self.edit_count;
+    cloned.offset = self.offset;
     cloned
   }
 
@@ -229,67 +439,172 @@ impl MagicString {
   }
 
   pub fn generate_decoded_map(&self, options: Option<GenerateMapOptions>) -> Result<DecodedMap> {
-    let GenerateMapOptions {
+    let options = options.unwrap_or_default();
+    let mappings = self._mappings(options.hires.unwrap_or_default(), "generateMap")?;
+    Ok(self._decoded_map(options, mappings))
+  }
+
+  pub fn generate(&self, options: Option<GenerateMapOptions>) -> Result<(String, SourceMap)> {
+    let (code, decoded_map) = self.generate_decoded(options)?;
+    Ok((code, SourceMap::from_decoded_map(decoded_map)?))
+  }
+
+  // builds the code and its map in a single traversal, or from whatever the render cache holds
+  pub fn generate_decoded(
+    &self,
+    options: Option<GenerateMapOptions>,
+  ) -> Result<(String, DecodedMap)> {
+    let options = options.unwrap_or_default();
+    let hires = options.hires.unwrap_or_default();
+    let cached = {
+      let render_cache = self.render_cache.borrow();
+      render_cache.code.is_some()
+        || matches!(render_cache.mappings, Some((cached_hires, _)) if cached_hires == hires)
+    };
+
+    let (code, mappings) = if cached {
+      (self.to_string(), self._mappings(hires, "generate")?)
+    } else {
+      let (code, mappings) = self._render_with_mappings(hires, "generate")?;
+      let mut render_cache = self.render_cache.borrow_mut();
+      render_cache.code = Some(code.clone());
+      render_cache.mappings = Some((hires, mappings.clone()));
+      (code, mappings)
+    };
+    Ok((code, self._decoded_map(options, mappings)))
+  }
+
+  fn _decoded_map(
+    &self,
+    GenerateMapOptions {
       file,
       source,
-      hires,
       include_content,
       source_root,
-    } = options.unwrap_or_default();
-    let hires = hires.unwrap_or_default();
-    let mut facade = MappingsFacade::new(hires, &self.sourcemap_locations);
-    facade.advance(self.intro.as_str());
-
-    Chunk::each_next(Rc::clone(&self.first_chunk), |chunk| {
-      let loc = self._locator.locate(chunk.borrow().start as usize);
-      if let Some((o_line, o_column)) = loc {
-        facade.add_mappings(
-          self.original.as_str(),
-          chunk.borrow().content.as_str(),
-          chunk.borrow().intro.as_str(),
-          chunk.borrow().outro.as_str(),
-          (o_line as u32, o_column as u32),
-          (chunk.borrow().start, chunk.borrow().end),
-          chunk.borrow().is_edited(),
-          self
-            .stored_names
-            .binary_search(&chunk.borrow().original)
-            .unwrap_or(usize::MAX),
-        );
-      }
-      Ok(false)
-    })?;
-    facade.advance(self.outro.as_str());
-
-    Ok(DecodedMap {
+      ..
+    }: GenerateMapOptions,
+    mappings: Mappings,
+  ) -> DecodedMap {
+    DecodedMap {
       version: SOURCEMAP_VERSION,
       file: file
         .as_ref()
-        .and_then(|x| x.split(&['/', '\\'][..]).last().map(String::from)),
+        .and_then(|x| x.split(&['/', '\\'][..]).next_back().map(String::from)),
       sources: vec![source
         .as_ref()
         .map(|x| get_relative_path(&file.unwrap_or_default(), x))
         .unwrap_or_default()],
       sources_content: include_content.and_then(|x| {
         if x {
-          Some(vec![self.original.to_owned()])
+          Some(vec![self.original.to_string()])
         } else {
           None
         }
       }),
       source_root,
       names: self.stored_names.to_owned(),
-      mappings: facade.get(),
+      mappings,
       x_google_ignoreList: if self.ignore_list {
         Some(vec![SOURCE_INDEX])
       } else {
         None
       },
-    })
+    }
+  }
+
+  fn _mappings(&self, hires: bool, api: &'static str) -> Result<Mappings> {
+    if let Some((cached_hires, mappings)) = &self.render_cache.borrow().mappings {
+      if *cached_hires == hires {
+        return Ok(mappings.clone());
+      }
+    }
+
+    let mut facade = MappingsFacade::new(hires, &self.sourcemap_locations);
+    facade.advance(self.intro.as_str());
+
+    Chunk::each_next(Rc::clone(&self.first_chunk), |chunk| {
+      self._add_chunk_mappings(&mut facade, &chunk.borrow(), api);
+      Ok(false)
+    })?;
+    facade.advance(self.outro.as_str());
+
+    let mappings = facade.get();
+    self.render_cache.borrow_mut().mappings = Some((hires, mappings.clone()));
+    Ok(mappings)
+  }
+
+  fn _render_with_mappings(&self, hires: bool, api: &'static str) -> Result<(String, Mappings)> {
+    let mut code = String::with_capacity(self.original.len());
+    let mut facade = MappingsFacade::new(hires, &self.sourcemap_locations);
+    code.push_str(&self.intro);
+    facade.advance(&self.intro);
+    Chunk::each_next(Rc::clone(&self.first_chunk), |chunk| {
+      let chunk = chunk.borrow();
+      code.push_str(&chunk.intro);
+      code.push_str(chunk.content());
+      code.push_str(&chunk.outro);
+      self._add_chunk_mappings(&mut facade, &chunk, api);
+      Ok(false)
+    })?;
+    code.push_str(&self.outro);
+    facade.advance(&self.outro);
+    Ok((code, facade.get()))
+  }
+
+  fn _add_chunk_mappings(&self, facade: &mut MappingsFacade, chunk: &Chunk, api: &'static str) {
+    // an edited chunk only maps its start, the locations asked for inside it are lost
+    if chunk.is_edited()
+      && self
+        .sourcemap_locations
+        .has_any(chunk.start as usize + 1, chunk.end as usize)
+    {
+      self._warn(
+        WarningCode::LossyMapping,
+        api,
+        &format!(
+          "{} can't map sourcemap locations inside edited content back to the original, {:?} is mapped from its start only",
+          api,
+          chunk.original()
+        ),
+      );
+    }
+    let loc = self
+      ._locator
+      .locate_utf16(&self.original, chunk.start as usize);
+    if let Some((o_line, o_column)) = loc {
+      facade.add_mappings(
+        &self.original,
+        chunk.content(),
+        chunk.intro.as_str(),
+        chunk.outro.as_str(),
+        (o_line as u32, o_column as u32),
+        (chunk.start, chunk.end),
+        chunk.is_edited(),
+        if chunk.store_name {
+          self
+            .stored_name_indices
+            .get(chunk.original())
+            .copied()
+            .unwrap_or(usize::MAX)
+        } else {
+          usize::MAX
+        },
+      );
+    }
+  }
+
+  fn _store_name(&mut self, name: String) {
+    if !self.stored_name_indices.contains_key(&name) {
+      self
+        .stored_name_indices
+        .insert(name.clone(), self.stored_names.len());
+      self.stored_names.push(name);
+    }
   }
 
   pub fn _move(&mut self, start: i32, end: i32, index: u32) -> Result<&mut Self> {
-    let (_start, _end) = normalize_range(self.original.as_str(), start, end)?;
+    let (_start, _end) = self._offset_range(start, end)?;
+    let index = self._offset_index(index)?;
 
     if index >= _start && index <= _end {
       return Err(Error::from_reason(
@@ -301,6 +616,7 @@ impl MagicString {
     self._split(_start)?;
     self._split(_end)?;
     self._split(index)?;
+    self._invalidate();
 
     let first = self
       .start_index_chunk_map
@@ -367,10 +683,21 @@ impl MagicString {
     end: i32,
     content: &str,
     options: Option<OverwriteOptions>,
+  ) -> Result<&mut Self> {
+    let (start, end) = self._offset_range(start, end)?;
+    self._overwrite(start, end, content, options)
+  }
+
+  fn _overwrite(
+    &mut self,
+    start: u32,
+    end: u32,
+    content: &str,
+    options: Option<OverwriteOptions>,
   ) -> Result<&mut Self> {
     let mut option = options.unwrap_or_default();
     option.overwrite = Some(!option.content_only.unwrap_or_default());
-    self.update(start, end, content, Some(option))
+    self._update(start, end, content, Some(option))
   }
 
   pub fn update(
@@ -379,13 +706,22 @@ impl MagicString {
     end: i32,
     content: &str,
     options: Option<OverwriteOptions>,
+  ) -> Result<&mut Self> {
+    let (start, end) = self._offset_range(start, end)?;
+    self._update(start, end, content, options)
+  }
+
+  fn _update(
+    &mut self,
+    _start: u32,
+    _end: u32,
+    content: &str,
+    options: Option<OverwriteOptions>,
   ) -> Result<&mut Self> {
     let option = options.unwrap_or_default();
     let store_name = option.store_name.unwrap_or_default();
     let content_only = option.content_only.unwrap_or_default();
 
-    let (_start, _end) = normalize_range(self.original.as_str(), start, end)?;
-
     if _start == _end {
       return Err(Error::from_reason(
         FmsErrType::Range,
@@ -395,23 +731,22 @@ impl MagicString {
 
     self._split(_start)?;
     self._split(_end)?;
+    self._invalidate();
 
     if store_name {
-      let original = self.original.clone();
-      self
-        .stored_names
-        .push(slice_string(original, _start as usize, _end as usize));
+      let name = slice_string(&self.original, _start as usize, _end as usize);
+      self._store_name(name);
     }
 
     let first = self.start_index_chunk_map.get(&_start);
     let last = self.end_index_chunk_map.get(&_end);
 
-    if first.is_some() && last.is_some() {
-      let first = Rc::clone(first.unwrap());
+    if let (Some(first), Some(last)) = (first, last) {
+      let first = Rc::clone(first);
       first
         .borrow_mut()
         .edit(content, store_name, !option.overwrite.unwrap_or(false));
-      let last = Rc::clone(last.unwrap());
+      let last = Rc::clone(last);
       let mut cur = Some(first);
       while cur.is_some() && cur.clone().unwrap() != last {
         let c = cur.as_ref().unwrap();
@@ -426,7 +761,7 @@ impl MagicString {
         cur = next;
       }
     } else {
-      let mut new_chunk = Chunk::new(_start, _end, "");
+      let mut new_chunk = Chunk::new(Arc::clone(&self.original), _start, _end);
       new_chunk.edit(content, store_name, content_only);
 
       if let Some(_last) = last {
@@ -439,12 +774,15 @@ impl MagicString {
   }
 
   pub fn prepend(&mut self, str: &str) -> Result<&mut Self> {
+    self._invalidate();
     self.intro = concat_string!(str, self.intro);
     Ok(self)
   }
 
   pub fn prepend_left(&mut self, index: u32, content: &str) -> Result<&mut Self> {
+    let index = self._offset_index(index)?;
     self._split(index)?;
+    self._invalidate();
     if let Some(chunk) = self.end_index_chunk_map.get(&index) {
       let mut chunk: std::cell::RefMut<'_, Chunk> = chunk.borrow_mut();
       chunk.prepend_left(content);
@@ -455,7 +793,12 @@ impl MagicString {
   }
 
   pub fn prepend_right(&mut self, index: u32, content: &str) -> Result<&mut Self> {
+    self._prepend_right(self._offset_index(index)?, content)
+  }
+
+  fn _prepend_right(&mut self, index: u32, content: &str) -> Result<&mut Self> {
     self._split(index)?;
+    self._invalidate();
     if let Some(chunk) = self.start_index_chunk_map.get(&index) {
       let mut chunk: std::cell::RefMut<'_, Chunk> = chunk.borrow_mut();
       chunk.prepend_right(content);
@@ -466,14 +809,18 @@ impl MagicString {
   }
 
   pub fn remove(&mut self, start: i32, end: i32) -> Result<&Self> {
-    let (_start, _end) = normalize_range(self.original.as_str(), start, end)?;
+    let (start, end) = self._offset_range(start, end)?;
+    self._remove(start, end)
+  }
 
+  fn _remove(&mut self, _start: u32, _end: u32) -> Result<&Self> {
     if _start == _end {
       return Ok(self);
     }
 
     self._split(_start)?;
     self._split(_end)?;
+    self._invalidate();
 
     let first = self.start_index_chunk_map.get(&_start);
     let last = self.end_index_chunk_map.get(&_end);
@@ -493,12 +840,13 @@ impl MagicString {
   }
 
   pub fn reset(&mut self, start: i32, end: i32) -> Result<&Self> {
-    let (_start, _end) = normalize_range(self.original.as_str(), start, end)?;
+    let (_start, _end) = self._offset_range(start, end)?;
     if _start == _end {
       return Ok(self);
     }
     self._split(_start)?;
     self._split(_end)?;
+    self._invalidate();
     let mut first = self.start_index_chunk_map.get(&_start).map(Rc::clone);
     while let Some(c) = first {
       c.borrow_mut().reset();
@@ -515,7 +863,11 @@ impl MagicString {
   }
 
   pub fn slice(&self, start: i32, end: i32) -> Result<String> {
-    let (_start, _end) = normalize_range(self.original.as_str(), start, end)?;
+    let (start, end) = self._offset_range(start, end)?;
+    self._slice(start, end)
+  }
+
+  fn _slice(&self, _start: u32, _end: u32) -> Result<String> {
     let mut s = String::new();
     let mut chunk = Some(Rc::clone(&self.first_chunk));
     while let Some(cur) = chunk.clone() {
@@ -563,11 +915,11 @@ impl MagicString {
           0
         };
         let slice_end = if contains_end {
-          (c.borrow().content.len()) as u32 + _end - c.borrow().end
+          (c.borrow().content().len()) as u32 + _end - c.borrow().end
         } else {
-          c.borrow().content.len() as u32
+          c.borrow().content().len() as u32
         };
-        s.push_str(&c.borrow().content.as_str()[slice_start as usize..slice_end as usize]);
+        s.push_str(&c.borrow().content()[slice_start as usize..slice_end as usize]);
 
         if !c.borrow().outro.is_empty() && (!contains_end || c.borrow().end == _end) {
           s.push_str(&c.borrow().outro);
@@ -580,9 +932,10 @@ impl MagicString {
   }
 
   pub fn snip(&mut self, start: i32, end: i32) -> Result<MagicString> {
+    let (start, end) = self._offset_range(start, end)?;
     let mut cloned = self._clone();
-    cloned.remove(0, start)?;
-    cloned.remove(end, cloned.original.len() as i32)?;
+    cloned._remove(0, start)?;
+    cloned._remove(end, cloned.original.len() as u32)?;
     Ok(cloned)
   }
 
@@ -604,6 +957,7 @@ impl MagicString {
         return Ok(self);
       }
     }
+    self._invalidate();
 
     let indent_str = indent_str.unwrap();
 
@@ -612,8 +966,17 @@ impl MagicString {
 
     if let Some(exclusions) = exclusions {
       for exclusion in exclusions {
-        let start = exclusion[0];
-        let end = exclusion[1];
+        let [start, end] = exclusion[..] else {
+          self._warn(
+            WarningCode::IgnoredOption,
+            "indent",
+            &format!(
+              "indent `exclude` ranges are [start, end] pairs, {:?} is ignored",
+              exclusion
+            ),
+          );
+          continue;
+        };
         for i in start..end {
           is_excluded_map.insert(i, true);
         }
@@ -622,38 +985,34 @@ impl MagicString {
 
     let mut should_indent_next_character = options.indent_start.unwrap_or(true);
 
-    let regexp = Regex::new(r"(?m)^[^\r\n]").unwrap();
+    let regexp = cached_regex(r"(?m)^[^\r\n]")?;
 
-    self.intro = regexp
-      .replace_all(&self.intro, |caps: &regex::Captures| {
-        if should_indent_next_character {
-          concat_string!(indent_str, &caps[0])
-        } else {
-          should_indent_next_character = true;
-          caps[0].to_string()
-        }
-      })
-      .into_owned();
+    self.intro = regexp.replace_all_with(&self.intro, |caps| {
+      if should_indent_next_character {
+        concat_string!(indent_str, &caps[0])
+      } else {
+        should_indent_next_character = true;
+        caps[0].to_string()
+      }
+    })?;
 
     let mut char_index = 0;
     let _ = Chunk::each_next(Rc::clone(&self.first_chunk), |chunk| {
-      let content = chunk.borrow().content.clone();
       if chunk.borrow().is_edited() {
         let is_excluded = is_excluded_map.get(&char_index).copied().unwrap_or(false);
         if !is_excluded {
-          chunk.borrow_mut().content = regexp
-            .replace_all(&content, |caps: &regex::Captures| {
-              if should_indent_next_character {
-                concat_string!(indent_str, &caps[0])
-              } else {
-                should_indent_next_character = true;
-                caps[0].to_string()
-              }
-            })
-            .into_owned();
-          if !chunk.borrow().content.is_empty() {
-            should_indent_next_character =
-              chunk.borrow().content.chars().last().unwrap_or_default() == '\n'
+          let content = regexp.replace_all_with(chunk.borrow().content(), |caps| {
+            if should_indent_next_character {
+              concat_string!(indent_str, &caps[0])
+            } else {
+              should_indent_next_character = true;
+              caps[0].to_string()
+            }
+          })?;
+          let store_name = chunk.borrow().store_name;
+          chunk.borrow_mut().edit(&content, store_name, true);
+          if !content.is_empty() {
+            should_indent_next_character = content.ends_with('\n')
           }
         }
       } else {
@@ -683,145 +1042,765 @@ impl MagicString {
       Ok(false)
     });
 
-    self.outro = regexp
-      .replace_all(&self.outro, |caps: &regex::Captures| {
-        if should_indent_next_character {
-          concat_string!(indent_str, &caps[0])
+    self.outro = regexp.replace_all_with(&self.outro, |caps| {
+      if should_indent_next_character {
+        concat_string!(indent_str, &caps[0])
+      } else {
+        should_indent_next_character = true;
+        caps[0].to_string()
+      }
+    })?;
+
+    Ok(self)
+  }
+
+  // `amount` defaults to the smallest indentation of the non-blank lines
+  pub fn dedent(&mut self, amount: Option<u32>, options: Option<IndentOptions>) -> Result<&Self> {
+    let options = options.unwrap_or_default();
+    let amount = match amount {
+      Some(amount) => amount as usize,
+      None => min_indent(&self.to_string()),
+    };
+    if amount == 0 {
+      return Ok(self);
+    }
+    self._invalidate();
+
+    let exclusions = self._exclusion_ranges("dedent", options.exclude);
+    let is_excluded = |index: u32| {
+      exclusions
+        .iter()
+        .any(|&(start, end)| index >= start && index < end)
+    };
+
+    let mut pending = if options.indent_start.unwrap_or(true) {
+      amount
+    } else {
+      0
+    };
+    self.intro = dedent_str(&self.intro, amount, &mut pending);
+
+    let mut removals: Vec<(u32, u32)> = vec![];
+    Chunk::each_next(Rc::clone(&self.first_chunk), |chunk| {
+      let start = chunk.borrow().start;
+      let intro = dedent_str(&chunk.borrow().intro, amount, &mut pending);
+      chunk.borrow_mut().intro = intro;
+      if chunk.borrow().is_edited() {
+        if is_excluded(start) {
+          pending = 0;
         } else {
-          should_indent_next_character = true;
-          caps[0].to_string()
+          let content = dedent_str(chunk.borrow().content(), amount, &mut pending);
+          let store_name = chunk.borrow().store_name;
+          chunk.borrow_mut().edit(&content, store_name, true);
         }
-      })
-      .into_owned();
+      } else {
+        for (i, c) in chunk.borrow().original().char_indices() {
+          let index = start + i as u32;
+          if is_excluded(index) && is_indent_char(c) {
+            pending = 0;
+          } else if dedent_char(c, amount, &mut pending) {
+            match removals.last_mut() {
+              Some((_, end)) if *end == index => *end = index + 1,
+              _ => removals.push((index, index + 1)),
+            }
+          }
+        }
+      }
+      let outro = dedent_str(&chunk.borrow().outro, amount, &mut pending);
+      chunk.borrow_mut().outro = outro;
+      Ok(false)
+    })?;
+
+    // unlike `remove`, keep the content inserted around the stripped indentation
+    for (start, end) in removals {
+      self._edit_content_only(start, end, "")?;
+    }
+    self.outro = dedent_str(&self.outro, amount, &mut pending);
+
+    Ok(self)
+  }
+
+  pub fn normalize_line_endings(&mut self, line_ending: LineEnding) -> Result<&Self> {
+    self._invalidate();
+    // the character rendered right after each piece, in render order
+    let mut after = vec![];
+    let mut next = None;
+    self._each_piece_rev(|piece| {
+      after.push(next);
+      next = piece.chars().next().or(next);
+      false
+    });
+    let mut after = after.into_iter().rev();
+    // the last character rendered so far, before normalizing
+    let mut before = None;
+    let mut edits = |piece: &str| {
+      let edits = line_ending_edits(piece, line_ending, before, after.next().flatten());
+      before = piece.chars().next_back().or(before);
+      edits
+    };
+
+    let intro = apply_line_ending_edits(&self.intro, &edits(&self.intro));
+    self.intro = intro;
+    let mut chunks = vec![];
+    Chunk::each_next(Rc::clone(&self.first_chunk), |chunk| {
+      chunks.push(chunk);
+      Ok(false)
+    })?;
+    for chunk in chunks {
+      let intro = {
+        let intro = &chunk.borrow().intro;
+        apply_line_ending_edits(intro, &edits(intro))
+      };
+      chunk.borrow_mut().intro = intro;
+
+      let content_edits = edits(chunk.borrow().content());
+      let last = if chunk.borrow().is_edited() {
+        let content = apply_line_ending_edits(chunk.borrow().content(), &content_edits);
+        let store_name = chunk.borrow().store_name;
+        chunk.borrow_mut().edit(&content, store_name, true);
+        chunk
+      } else {
+        self._edit_line_breaks(chunk, content_edits)?
+      };
+
+      let outro = {
+        let outro = &last.borrow().outro;
+        apply_line_ending_edits(outro, &edits(outro))
+      };
+      last.borrow_mut().outro = outro;
+    }
+    let outro = apply_line_ending_edits(&self.outro, &edits(&self.outro));
+    self.outro = outro;
+
+    Ok(self)
+  }
+
+  // splits `chunk` around each edited line break in one pass, returns the part that now ends
+  // where `chunk` ended
+  fn _edit_line_breaks(
+    &mut self,
+    chunk: Rc<RefCell<Chunk>>,
+    edits: Vec<(usize, &str)>,
+  ) -> Result<Rc<RefCell<Chunk>>> {
+    let start = chunk.borrow().start;
+    let mut cur = chunk;
+    for (offset, content) in edits {
+      let index = start + offset as u32;
+      if index > cur.borrow().start {
+        self._split_chunk(Rc::clone(&cur), index)?;
+        let next = cur.borrow().next.clone().unwrap();
+        cur = next;
+      }
+      let rest = if index + 1 < cur.borrow().end {
+        self._split_chunk(Rc::clone(&cur), index + 1)?;
+        cur.borrow().next.clone()
+      } else {
+        None
+      };
+      cur.borrow_mut().edit(content, false, true);
+      if let Some(rest) = rest {
+        cur = rest;
+      }
+    }
+    Ok(cur)
+  }
+
+  // `from` defaults to the guessed indentation, only lines inside `start..end` are touched
+  // when a range is given
+  pub fn reindent(
+    &mut self,
+    from: Option<IndentStyle>,
+    to: IndentStyle,
+    options: Option<ReindentOptions>,
+  ) -> Result<&Self> {
+    let options = options.unwrap_or_default();
+    let from = match from {
+      Some(from) => from,
+      None => {
+        self._ensure_indent_str()?;
+        IndentStyle::from_indent_str(self.indent_str.as_deref().unwrap_or_default())
+      }
+    };
+    if from == to || from == IndentStyle::Spaces(0) {
+      return Ok(self);
+    }
+    let whole = options.start.is_none() && options.end.is_none();
+    let (start, end) = normalize_range(
+      &self.original,
+      options.start.unwrap_or(0),
+      options.end.unwrap_or(self.original.len() as i32),
+    )?;
+    self._invalidate();
+
+    let to_str = to.as_string();
+    let exclusions = self._exclusion_ranges("reindent", options.exclude);
+    let is_included = |index: u32| {
+      index >= start
+        && index < end
+        && !exclusions
+          .iter()
+          .any(|&(start, end)| index >= start && index < end)
+    };
+
+    let mut at_line_start = true;
+    if whole {
+      self.intro = reindent_str(&self.intro, from, &to_str, &mut at_line_start);
+    } else {
+      indent_units(&self.intro, from, &mut at_line_start);
+    }
+
+    let mut edits: Vec<(u32, u32)> = vec![];
+    Chunk::each_next(Rc::clone(&self.first_chunk), |chunk| {
+      let mut chunk = chunk.borrow_mut();
+      let included = is_included(chunk.start);
+      let reindent_inserted = |s: &str, at_line_start: &mut bool| {
+        if included {
+          reindent_str(s, from, &to_str, at_line_start)
+        } else {
+          indent_units(s, from, at_line_start);
+          s.to_string()
+        }
+      };
+      chunk.intro = reindent_inserted(&chunk.intro, &mut at_line_start);
+      if chunk.is_edited() {
+        let content = reindent_inserted(chunk.content(), &mut at_line_start);
+        let store_name = chunk.store_name;
+        chunk.edit(&content, store_name, true);
+      } else {
+        for (unit_start, unit_end) in indent_units(chunk.original(), from, &mut at_line_start) {
+          let unit_start = chunk.start + unit_start as u32;
+          if is_included(unit_start) {
+            edits.push((unit_start, chunk.start + unit_end as u32));
+          }
+        }
+      }
+      chunk.outro = reindent_inserted(&chunk.outro, &mut at_line_start);
+      Ok(false)
+    })?;
+
+    // each unit becomes its own edited chunk so the tokens after it keep their original columns
+    for (start, end) in edits {
+      self._edit_content_only(start, end, &to_str)?;
+    }
+    if whole {
+      self.outro = reindent_str(&self.outro, from, &to_str, &mut at_line_start);
+    }
 
     Ok(self)
   }
 
+  // replaces `start..end` without touching the content inserted around it
+  fn _edit_content_only(&mut self, start: u32, end: u32, content: &str) -> Result<()> {
+    self._split(start)?;
+    self._split(end)?;
+    let mut content = content;
+    let mut cur = self.start_index_chunk_map.get(&start).map(Rc::clone);
+    while let Some(c) = cur {
+      c.borrow_mut().edit(content, false, true);
+      content = "";
+      let chunk_end = c.borrow().end;
+      cur = if chunk_end < end {
+        self.start_index_chunk_map.get(&chunk_end).map(Rc::clone)
+      } else {
+        None
+      };
+    }
+    Ok(())
+  }
+
+  fn _exclusion_ranges(
+    &self,
+    api: &'static str,
+    exclude: Option<Vec<Vec<u32>>>,
+  ) -> Vec<(u32, u32)> {
+    let mut ranges = match &self._raw_options.indent_exclusion_ranges {
+      Some(IndentExclusionRanges::Single(range)) => vec![range.clone()],
+      Some(IndentExclusionRanges::Nested(ranges)) => ranges.clone(),
+      None => vec![],
+    };
+    ranges.extend(exclude.unwrap_or_default());
+    ranges
+      .iter()
+      .filter_map(|range| match range[..] {
+        [start, end] => Some((start, end)),
+        _ => {
+          self._warn(
+            WarningCode::IgnoredOption,
+            api,
+            &format!(
+              "{} exclusion ranges are [start, end] pairs, {:?} is ignored",
+              api, range
+            ),
+          );
+          None
+        }
+      })
+      .collect()
+  }
+
   pub fn is_empty(&self) -> bool {
     self.to_string().trim().is_empty()
   }
 
-  pub fn trim(&mut self, char_type: Option<&str>) {
-    self.trim_start(char_type).trim_end(char_type);
+  pub fn trim(&mut self, char_type: Option<&str>) -> Result<&mut Self> {
+    self.trim_start(char_type)?.trim_end(char_type)
   }
 
-  pub fn trim_start_aborted(&mut self, char_type: Option<&str>) -> bool {
+  pub fn trim_start_aborted(&mut self, char_type: Option<&str>) -> Result<bool> {
     let pat = "^".to_owned() + char_type.unwrap_or("\\s") + "+";
-    let regexp = Regex::new(pat.as_str()).unwrap();
-    self.intro = regexp.replace(&self.intro, "").to_string();
+    let regexp = cached_regex(pat.as_str())?;
+    self._invalidate();
+    self.intro = regexp.replace(&self.intro, "")?;
     if !self.intro.is_empty() {
-      return true;
+      return Ok(true);
     }
     let mut cur = Some(Rc::clone(&self.first_chunk));
 
     while let Some(c) = cur {
       let mut _cur = c.borrow_mut();
       // let end = _cur.end;
-      let aborted = _cur.trim_start(&regexp);
+      let aborted = _cur.trim_start(&regexp)?;
       if aborted {
-        return true;
+        return Ok(true);
       }
       cur = _cur.next.clone();
     }
 
-    false
+    Ok(false)
   }
 
-  pub fn trim_start(&mut self, char_type: Option<&str>) -> &mut Self {
-    self.trim_start_aborted(char_type);
-    self
+  pub fn trim_start(&mut self, char_type: Option<&str>) -> Result<&mut Self> {
+    self.trim_start_aborted(char_type)?;
+    Ok(self)
   }
 
-  pub fn trim_end_aborted(&mut self, char_type: Option<&str>) -> bool {
+  pub fn trim_end_aborted(&mut self, char_type: Option<&str>) -> Result<bool> {
     let pat = char_type.unwrap_or("\\s").to_owned() + "+$";
-    let regexp = Regex::new(pat.as_str()).unwrap();
-    self.outro = regexp.replace(&self.outro, "").to_string();
+    let regexp = cached_regex(pat.as_str())?;
+    self._invalidate();
+    self.outro = regexp.replace(&self.outro, "")?;
     if !self.outro.is_empty() {
-      return true;
+      return Ok(true);
     }
     let mut cur = Some(Rc::clone(&self.last_chunk));
 
     while let Some(c) = cur {
       let mut _cur = c.borrow_mut();
       // let end = _cur.end;
-      let aborted = _cur.trim_end(&regexp);
+      let aborted = _cur.trim_end(&regexp)?;
       if aborted {
-        return true;
+        return Ok(true);
       }
       cur = _cur.previous.as_ref().map(Rc::clone);
     }
 
-    false
+    Ok(false)
   }
 
-  pub fn trim_end(&mut self, char_type: Option<&str>) -> &mut Self {
-    self.trim_end_aborted(char_type);
-    self
+  pub fn trim_end(&mut self, char_type: Option<&str>) -> Result<&mut Self> {
+    self.trim_end_aborted(char_type)?;
+    Ok(self)
   }
 
-  pub fn trim_lines(&mut self) -> &mut Self {
-    self.trim(Some("[\\r\\n]"));
-    self
+  pub fn trim_lines(&mut self) -> Result<&mut Self> {
+    self.trim(Some("[\\r\\n]"))
   }
 
+  // compares the edit state of the chunks instead of rendering, cached until the next edit
   pub fn has_changed(&self) -> bool {
-    self.original != self.to_string()
+    if self.edit_count == 0 {
+      return false;
+    }
+    if let Some(changed) = self.render_cache.borrow().changed {
+      return changed;
+    }
+    let changed = self._has_changed();
+    self.render_cache.borrow_mut().changed = Some(changed);
+    changed
   }
 
-  pub fn replace_by_regexp(
-    &mut self,
-    search_value: &str,
-    replacement: &str,
-    global: bool,
-  ) -> Result<&Self> {
-    let this = self as *mut Self;
-
-    let regexp = Regex::new(search_value).unwrap();
-    let str = self.original.as_str();
-    let matches = match_all(&regexp, str, global);
-
-    let get_replacement = |match_item: &Captures| {
-      let regexp = Regex::new(r"\$(\$|&|(\d+))").unwrap();
-      regexp.replace(replacement, |caps: &Captures| {
-        let matched = &caps[0];
-        let i = &caps[1];
-        match i {
-          "$" => "$".to_string(),
-          "&" => matched.to_string(),
-          num_str => {
-            if let Ok(num) = num_str.parse::<usize>() {
-              if num < match_item.len() {
-                match_item.get(num).unwrap().as_str().to_string()
-              } else {
-                format!("${}", i)
-              }
-            } else {
-              format!("${}", i)
-            }
-          }
-        }
+  // unchanged when no content was added and the chunks still cover the original in order
+  fn _has_changed(&self) -> bool {
+    if !self.intro.is_empty() || !self.outro.is_empty() {
+      return true;
+    }
+    let mut expected_start = 0;
+    let mut changed = false;
+    let _ = Chunk::each_next(Rc::clone(&self.first_chunk), |chunk| {
+      let chunk = chunk.borrow();
+      changed = chunk.start != expected_start
+        || !chunk.intro.is_empty()
+        || !chunk.outro.is_empty()
+        || (chunk.is_edited() && chunk.content() != chunk.original());
+      expected_start = chunk.end;
+      Ok(changed)
+    });
+    changed || expected_start as usize != self.original.len()
+  }
+
+  pub fn edit_count(&self) -> u64 {
+    self.edit_count
+  }
+
+  pub fn offset(&self) -> u32 {
+    self.offset
+  }
+
+  pub fn set_offset(&mut self, offset: u32) -> &mut Self {
+    self.offset = offset;
+    self
+  }
+
+  // length of the generated string in UTF-16 code units like magic-string's `length()`,
+  // reuses the last render when there is one
+  pub fn len(&self) -> usize {
+    if let Some(code) = &self.render_cache.borrow().code {
+      return code.encode_utf16().count();
+    }
+    let mut len = 0;
+    self._each_piece_rev(|piece| {
+      len += piece.encode_utf16().count();
+      false
+    });
+    len
+  }
+
+  pub fn last_char(&self) -> Option<char> {
+    let mut last = None;
+    self._each_piece_rev(|piece| {
+      last = piece.chars().next_back();
+      last.is_some()
+    });
+    last
+  }
+
+  // the generated text after the last line break
+  pub fn last_line(&self) -> String {
+    let mut pieces = vec![];
+    self._each_piece_rev(|piece| match piece.rfind('\n') {
+      Some(index) => {
+        pieces.push(piece[index + 1..].to_string());
+        true
+      }
+      None => {
+        pieces.push(piece.to_string());
+        false
+      }
+    });
+    pieces.iter().rev().map(String::as_str).collect()
+  }
+
+  pub fn get_indent_string(&mut self) -> Result<&str> {
+    self._ensure_indent_str()?;
+    Ok(self.indent_str.as_deref().unwrap_or("\t"))
+  }
+
+  pub fn replace_by_regexp(
+    &mut self,
+    search_value: &str,
+    replacement: &str,
+    global: bool,
+  ) -> Result<&Self> {
+    self._replace_by_regexp(search_value, replacement, &mut RegexState::new(global))
+  }
+
+  fn _replace_by_regexp(
+    &mut self,
+    search_value: &str,
+    replacement: &str,
+    state: &mut RegexState,
+  ) -> Result<&Self> {
+    let regexp = cached_regex(search_value)?;
+    let template = ReplacementTemplate::parse(replacement, &regexp.capture_names());
+    let original = Arc::clone(&self.original);
+    let matches = state.captures(&regexp, &original)?;
+
+    // match offsets are already absolute, so `offset` does not apply
+    for caps in matches.iter() {
+      let m = caps.get(0).unwrap();
+      self._overwrite(
+        m.start() as u32,
+        m.end() as u32,
+        &template.expand(caps),
+        None,
+      )?;
+    }
+    Ok(self)
+  }
+
+  pub fn find(&self, pattern: Pattern, options: Option<FindOptions>) -> Result<Option<FindMatch>> {
+    Ok(self._find(pattern, options, false)?.pop())
+  }
+
+  pub fn find_all(&self, pattern: Pattern, options: Option<FindOptions>) -> Result<Vec<FindMatch>> {
+    self._find(pattern, options, true)
+  }
+
+  // `String.prototype.matchAll` over the original string, `start` is the `lastIndex` of the
+  // RegExp in UTF-16 code units and `sticky` its flag, match offsets are bytes
+  pub fn match_all(&self, source: &str, start: u32, sticky: bool) -> Result<MatchAll> {
+    let regexp = cached_regex(source)?;
+    let start = utf16_to_byte_index(&self.original, start as usize);
+    let captures = if !self.original.is_char_boundary(start) {
+      vec![]
+    } else if sticky {
+      regexp.captures_all_sticky(&self.original, start, true)?
+    } else {
+      regexp.captures_all(&self.original, start, true)?
+    };
+    Ok(MatchAll {
+      names: regexp.capture_names().into_iter().skip(1).collect(),
+      matches: captures
+        .iter()
+        .map(|caps| FindMatch::from_captures(caps, None))
+        .collect(),
+    })
+  }
+
+  fn _find(
+    &self,
+    pattern: Pattern,
+    options: Option<FindOptions>,
+    global: bool,
+  ) -> Result<Vec<FindMatch>> {
+    let options = options.unwrap_or_default();
+    let regexp = match pattern {
+      Pattern::String(s) => cached_regex(&fms_regexp::escape(s))?,
+      Pattern::Regex(source) => cached_regex(source)?,
+    };
+
+    if options.generated.unwrap_or(false) {
+      let code = self.to_string();
+      let position_index = self._position_index();
+      Ok(
+        match_all(&regexp, &code, global)?
+          .iter()
+          .map(|caps| FindMatch::from_captures(caps, Some(&position_index)))
+          .collect(),
+      )
+    } else {
+      Ok(
+        match_all(&regexp, &self.original, global)?
+          .iter()
+          .map(|caps| FindMatch::from_captures(caps, None))
+          .collect(),
+      )
+    }
+  }
+
+  pub fn replace_with_target(
+    &mut self,
+    pattern: Pattern,
+    replacement: &str,
+    global: bool,
+    target: ReplaceTarget,
+  ) -> Result<&Self> {
+    match (target, pattern) {
+      (ReplaceTarget::Original, Pattern::String(s)) if global => {
+        self.replace_all_by_string(s, replacement)
+      }
+      (ReplaceTarget::Original, Pattern::String(s)) => self.replace_by_string(s, replacement),
+      (target, Pattern::Regex(source)) => {
+        self.replace_regex(source, replacement, &mut RegexState::new(global), target)
+      }
+      (ReplaceTarget::Generated, pattern) => {
+        self._replace_generated(pattern, replacement, &mut RegexState::new(global))
+      }
+    }
+  }
+
+  // replaces like `String.prototype.replace` with a RegExp, honoring the sticky flag and
+  // updating `state.last_index`
+  pub fn replace_regex(
+    &mut self,
+    source: &str,
+    replacement: &str,
+    state: &mut RegexState,
+    target: ReplaceTarget,
+  ) -> Result<&Self> {
+    match target {
+      ReplaceTarget::Original => self._replace_by_regexp(source, replacement, state),
+      ReplaceTarget::Generated => {
+        self._replace_generated(Pattern::Regex(source), replacement, state)
+      }
+    }
+  }
+
+  fn _replace_generated(
+    &mut self,
+    pattern: Pattern,
+    replacement: &str,
+    state: &mut RegexState,
+  ) -> Result<&Self> {
+    let (regexp, template) = match pattern {
+      Pattern::String(s) => (cached_regex(&fms_regexp::escape(s))?, None),
+      Pattern::Regex(source) => {
+        let regexp = cached_regex(source)?;
+        let template = ReplacementTemplate::parse(replacement, &regexp.capture_names());
+        (regexp, Some(template))
+      }
+    };
+    let code = self.to_string();
+    let matches: Vec<(usize, usize, String)> = state
+      .captures(&regexp, &code)?
+      .iter()
+      .map(|caps| {
+        let m = caps.get(0).unwrap();
+        let replacement = match &template {
+          Some(template) => template.expand(caps),
+          None => replacement.to_string(),
+        };
+        (m.start(), m.end(), replacement)
       })
+      .collect();
+    if matches.is_empty() {
+      return Ok(self);
+    }
+    self._invalidate();
+
+    // every non-empty piece of the output with its generated offset
+    let mut slots: Vec<(usize, usize, Slot)> = vec![];
+    let mut offset = 0;
+    let mut push = |slot: Slot, len: usize| {
+      if len > 0 {
+        slots.push((offset, offset + len, slot));
+        offset += len;
+      }
     };
+    push(Slot::Intro, self.intro.len());
+    Chunk::each_next(Rc::clone(&self.first_chunk), |chunk| {
+      let c = chunk.borrow();
+      push(Slot::ChunkIntro(Rc::clone(&chunk)), c.intro.len());
+      push(Slot::ChunkContent(Rc::clone(&chunk)), c.content().len());
+      push(Slot::ChunkOutro(Rc::clone(&chunk)), c.outro.len());
+      Ok(false)
+    })?;
+    push(Slot::Outro, self.outro.len());
+
+    // edits of inserted strings, as (slot, start, end, content) relative to the slot
+    let mut inserted_edits: Vec<(&Slot, usize, usize, String)> = vec![];
+    // edits of unedited original content, as (start, end, content) in original indices
+    let mut original_edits: Vec<(u32, u32, String)> = vec![];
+    let mut i = 0;
+    for (start, end, replacement) in matches {
+      while i < slots.len() && slots[i].1 <= start {
+        i += 1;
+      }
+      let mut replacement = Some(replacement);
+      let mut j = i;
+      loop {
+        let Some((slot_start, slot_end, slot)) = slots.get(j) else {
+          // empty match at the end of the output
+          self.outro.push_str(&replacement.take().unwrap_or_default());
+          break;
+        };
+        let from = start.max(*slot_start) - slot_start;
+        let to = end.min(*slot_end) - slot_start;
+        let content = replacement.take().unwrap_or_default();
+        match slot {
+          Slot::ChunkContent(chunk) if !chunk.borrow().is_edited() => {
+            let chunk_start = chunk.borrow().start;
+            original_edits.push((chunk_start + from as u32, chunk_start + to as u32, content));
+          }
+          _ => inserted_edits.push((slot, from, to, content)),
+        }
+        if *slot_end >= end {
+          break;
+        }
+        j += 1;
+      }
+    }
+
+    // edits within a slot are applied back to front so their offsets stay valid
+    for (slot, from, to, content) in inserted_edits.into_iter().rev() {
+      let splice = |s: &str| concat_string!(&s[..from], content, &s[to..]);
+      match slot {
+        Slot::Intro => self.intro = splice(&self.intro),
+        Slot::Outro => self.outro = splice(&self.outro),
+        Slot::ChunkIntro(chunk) => {
+          let intro = splice(&chunk.borrow().intro);
+          chunk.borrow_mut().intro = intro;
+        }
+        Slot::ChunkContent(chunk) => {
+          let content = splice(chunk.borrow().content());
+          let store_name = chunk.borrow().store_name;
+          chunk.borrow_mut().edit(&content, store_name, true);
+        }
+        Slot::ChunkOutro(chunk) => {
+          let outro = splice(&chunk.borrow().outro);
+          chunk.borrow_mut().outro = outro;
+        }
+      }
+    }
+    // splitting moves outros to the new chunk, so original content is edited last
+    for (start, end, content) in original_edits {
+      if start == end {
+        self._split(start)?;
+        if let Some(chunk) = self.start_index_chunk_map.get(&start) {
+          chunk.borrow_mut().append_right(&content);
+        }
+      } else {
+        self._edit_content_only(start, end, &content)?;
+      }
+    }
 
-    for (idx, caps) in matches.0.iter().enumerate() {
-      let _replacement = get_replacement(caps);
-      let offset = matches.1.get(idx).unwrap_or(&0);
-      let start = (caps.get(0).unwrap().start() + *offset) as i32;
-      let end = (caps.get(0).unwrap().end() + *offset) as i32;
-      unsafe {
-        (*this).overwrite(start, end, _replacement.into_owned().as_str(), None)?;
+    Ok(self)
+  }
+
+  // only matches that lie entirely inside the original `start..end` are replaced
+  pub fn replace_in_range(
+    &mut self,
+    start: i32,
+    end: i32,
+    pattern: Pattern,
+    replacement: &str,
+    global: bool,
+  ) -> Result<&Self> {
+    let (start, end) = normalize_range(&self.original, start, end)?;
+    if start > end {
+      return Err(Error::from_reason(
+        FmsErrType::Range,
+        "start must not be greater than end",
+      ));
+    }
+    let (regexp, template) = match pattern {
+      Pattern::String(s) => (cached_regex(&fms_regexp::escape(s))?, None),
+      Pattern::Regex(source) => {
+        let regexp = cached_regex(source)?;
+        let template = ReplacementTemplate::parse(replacement, &regexp.capture_names());
+        (regexp, Some(template))
       }
+    };
+
+    let original = Arc::clone(&self.original);
+    for caps in match_all_in(&regexp, &original, start as usize, end as usize, global)? {
+      let m = caps.get(0).unwrap();
+      let content = match &template {
+        Some(template) => template.expand(&caps),
+        None => replacement.to_string(),
+      };
+      self._overwrite(m.start() as u32, m.end() as u32, &content, None)?;
     }
     Ok(self)
   }
 
+  pub fn replace_all_in_range(
+    &mut self,
+    start: i32,
+    end: i32,
+    pattern: Pattern,
+    replacement: &str,
+  ) -> Result<&Self> {
+    self.replace_in_range(start, end, pattern, replacement, true)
+  }
+
   pub fn replace_by_string(&mut self, search_value: &str, replacement: &str) -> Result<&Self> {
     let start = self.original.find(search_value);
 
     if let Some(start) = start {
-      self.overwrite(
-        start as i32,
-        (start + search_value.chars().count()) as i32,
+      self._overwrite(
+        start as u32,
+        (start + search_value.chars().count()) as u32,
         replacement,
         None,
       )?;
@@ -830,13 +1809,74 @@ impl MagicString {
     Ok(self)
   }
 
+  // replaces every pattern in a single pass over the original string
+  pub fn replace_many(
+    &mut self,
+    replacements: &[(&str, &str)],
+    options: Option<ReplaceManyOptions>,
+  ) -> Result<&Self> {
+    let options = options.unwrap_or_default();
+    let patterns: Vec<&str> = replacements.iter().map(|(pattern, _)| *pattern).collect();
+    let original = Arc::clone(&self.original);
+    let matches = if options.word_boundary.unwrap_or(false) {
+      find_words(&original, &patterns)?
+    } else {
+      find_many(&original, &patterns, None)?
+    };
+
+    for (start, end, i) in matches {
+      self._overwrite(start as u32, end as u32, replacements[i].1, None)?;
+    }
+    Ok(self)
+  }
+
+  // renames whole identifiers, the original names end up in the sourcemap `names`
+  pub fn replace_identifiers(
+    &mut self,
+    replacements: &[(&str, &str)],
+    options: Option<IdentifierOptions>,
+  ) -> Result<&Self> {
+    let options = options.unwrap_or_default();
+    let names: Vec<&str> = replacements.iter().map(|(name, _)| *name).collect();
+    let original = Arc::clone(&self.original);
+    let matches = find_identifiers(&original, &names, &options)?;
+    // keys are only renamed on request, shorthand keeps the key and renames the value
+    let keep_keys = options.skip_property_keys.unwrap_or(true);
+
+    for (start, end, i) in matches {
+      let (name, replacement) = replacements[i];
+      let shorthand = if keep_keys {
+        shorthand(&original, start, end)
+      } else {
+        None
+      };
+      let content = match shorthand {
+        None => replacement.to_string(),
+        Some(Shorthand::Property) => concat_string!(name, ": ", replacement),
+        Some(Shorthand::Import) => concat_string!(name, " as ", replacement),
+        Some(Shorthand::Export) => concat_string!(replacement, " as ", name),
+        Some(Shorthand::Foreign) => continue,
+      };
+      self._overwrite(
+        start as u32,
+        end as u32,
+        &content,
+        Some(OverwriteOptions {
+          store_name: Some(true),
+          ..Default::default()
+        }),
+      )?;
+    }
+    Ok(self)
+  }
+
   pub fn replace_all_by_string(&mut self, search_value: &str, replacement: &str) -> Result<&Self> {
     let mut start = self.original.find(search_value);
     let mut offset: usize = 0;
     while let Some(_start) = start {
       let _start = _start + offset;
       offset = _start + search_value.len();
-      self.overwrite(_start as i32, offset as i32, replacement, None)?;
+      self._overwrite(_start as u32, offset as u32, replacement, None)?;
       start = if offset <= self.original.len() {
         self.original[offset..].find(search_value)
       } else {
@@ -847,6 +1887,171 @@ impl MagicString {
     Ok(self)
   }
 
+  // the byte index of a line and a column in UTF-16 code units, as editors and source maps
+  // count columns
+  pub fn index_of(&self, line: u32, column: u32) -> Result<u32> {
+    let line_base = self._line_base();
+    let index = if line >= line_base {
+      self
+        ._locator
+        .index_of_utf16(&self.original, (line - line_base) as usize, column as usize)
+    } else {
+      None
+    };
+    match index {
+      Some(index) => Ok(index as u32),
+      None => Err(Error::from_reason(
+        FmsErrType::Range,
+        format!(
+          "Position {}:{} is out of range or inside a character",
+          line, column
+        )
+        .as_str(),
+      )),
+    }
+  }
+
+  // inverse of `index_of`, the index must be on a character boundary
+  pub fn position_of(&self, index: u32) -> Result<(u32, u32)> {
+    let loc = if self.original.is_char_boundary(index as usize) {
+      self._locator.locate_utf16(&self.original, index as usize)
+    } else {
+      None
+    };
+    match loc {
+      Some((line, column)) => Ok((line as u32 + self._line_base(), column as u32)),
+      None => Err(Error::from_reason(
+        FmsErrType::Range,
+        format!("Index {} is out of range", index).as_str(),
+      )),
+    }
+  }
+
+  pub fn overwrite_at(
+    &mut self,
+    (start_line, start_column): (u32, u32),
+    (end_line, end_column): (u32, u32),
+    content: &str,
+    options: Option<OverwriteOptions>,
+  ) -> Result<&mut Self> {
+    let start = self.index_of(start_line, start_column)?;
+    let end = self.index_of(end_line, end_column)?;
+    self._overwrite(start, end, content, options)
+  }
+
+  pub fn remove_at(
+    &mut self,
+    (start_line, start_column): (u32, u32),
+    (end_line, end_column): (u32, u32),
+  ) -> Result<&Self> {
+    let start = self.index_of(start_line, start_column)?;
+    let end = self.index_of(end_line, end_column)?;
+    self._remove(start, end)
+  }
+
+  pub fn append_left_at(&mut self, (line, column): (u32, u32), content: &str) -> Result<&mut Self> {
+    let index = self.index_of(line, column)?;
+    self._append_left(index, content)
+  }
+
+  pub fn prepend_right_at(
+    &mut self,
+    (line, column): (u32, u32),
+    content: &str,
+  ) -> Result<&mut Self> {
+    let index = self.index_of(line, column)?;
+    self._prepend_right(index, content)
+  }
+
+  pub fn slice_at(
+    &self,
+    (start_line, start_column): (u32, u32),
+    (end_line, end_column): (u32, u32),
+  ) -> Result<String> {
+    let start = self.index_of(start_line, start_column)?;
+    let end = self.index_of(end_line, end_column)?;
+    self._slice(start, end)
+  }
+
+  pub fn chunks(&self) -> Chunks {
+    Chunks::new(Rc::clone(&self.first_chunk), self.intro.len() as u32)
+  }
+
+  pub fn generated_position_for(&self, original_index: u32) -> Result<MappedPosition> {
+    if original_index as usize > self.original.len() {
+      return Err(Error::from_reason(
+        FmsErrType::Range,
+        format!("Index {} is out of range", original_index).as_str(),
+      ));
+    }
+    Ok(
+      self
+        ._position_index()
+        .generated_position_for(original_index),
+    )
+  }
+
+  pub fn original_position_for(&self, generated_index: u32) -> Result<MappedPosition> {
+    let position_index = self._position_index();
+    if generated_index > position_index.len() {
+      return Err(Error::from_reason(
+        FmsErrType::Range,
+        format!("Index {} is out of range", generated_index).as_str(),
+      ));
+    }
+    Ok(position_index.original_position_for(generated_index))
+  }
+
+  fn _position_index(&self) -> Rc<PositionIndex> {
+    let mut position_index = self.position_index.borrow_mut();
+    Rc::clone(position_index.get_or_insert_with(|| {
+      Rc::new(PositionIndex::new(
+        &self.intro,
+        Rc::clone(&self.first_chunk),
+        &self.outro,
+      ))
+    }))
+  }
+
+  fn _invalidate(&mut self) {
+    self.edit_count += 1;
+    self.position_index.get_mut().take();
+    *self.render_cache.get_mut() = RenderCache::default();
+  }
+
+  fn _line_base(&self) -> u32 {
+    u32::from(self._raw_options.one_based_lines.unwrap_or_default())
+  }
+
+  // magic-string indices given to the public API are relative to `offset`
+  fn _offset_range(&self, start: i32, end: i32) -> Result<(u32, u32)> {
+    let offset = i32::try_from(self.offset).map_err(|_| self._offset_overflow())?;
+    match (start.checked_add(offset), end.checked_add(offset)) {
+      (Some(start), Some(end)) => normalize_range(&self.original, start, end),
+      _ => Err(self._offset_overflow()),
+    }
+  }
+
+  fn _offset_index(&self, index: u32) -> Result<u32> {
+    let index = index
+      .checked_add(self.offset)
+      .ok_or_else(|| self._offset_overflow())?;
+    if (index as usize) < self.original.len() && !self.original.is_char_boundary(index as usize) {
+      return Err(Error::from_reason(
+        FmsErrType::Range,
+        format!("Index {} is inside a character", index).as_str(),
+      ));
+    }
+    Ok(index)
+  }
+
+  fn _offset_overflow(&self) -> Error {
+    Error::from_reason(
+      FmsErrType::Range,
+      &format!("index is out of range with offset {}", self.offset),
+    )
+  }
+
   fn _ensure_indent_str(&mut self) -> Result<()> {
     if self.indent_str.is_none() {
       self.indent_str = Some(guess_indent(&self.original)?);
@@ -892,7 +2097,8 @@ impl MagicString {
   }
 
   fn _split_chunk(&mut self, chunk: Rc<RefCell<Chunk>>, index: u32) -> Result<()> {
-    if chunk.borrow().is_edited() && !chunk.borrow().content.is_empty() {
+    self.position_index.get_mut().take();
+    if chunk.borrow().is_edited() && !chunk.borrow().content().is_empty() {
       if let Some((line, column)) = self._locator.locate(index as usize) {
         return Err(Error::from_reason(
           FmsErrType::SplitChunk,
@@ -900,7 +2106,7 @@ impl MagicString {
             "Cannot split a chunk that has already been edited ({}:{} – '{}')",
             line,
             column,
-            chunk.borrow().original
+            chunk.borrow().original()
           )
           .as_str(),
         ));
@@ -927,15 +2133,568 @@ impl MagicString {
     Ok(())
   }
 
+  #[allow(clippy::inherent_to_string)]
   pub fn to_string(&self) -> String {
+    self
+      .render_cache
+      .borrow_mut()
+      .code
+      .get_or_insert_with(|| self._render())
+      .clone()
+  }
+
+  // only a cached render is copied, otherwise the rendered string gives up its buffer
+  pub fn to_bytes(&self) -> Vec<u8> {
+    match &self.render_cache.borrow().code {
+      Some(code) => code.as_bytes().to_vec(),
+      None => self._render().into_bytes(),
+    }
+  }
+
+  // the `mappings` field of the source map as UTF-8, without building the map, only
+  // `hires` affects the mappings so the other options are ignored
+  pub fn generate_encoded_mappings(&self, options: Option<GenerateMapOptions>) -> Result<Vec<u8>> {
+    let hires = options.unwrap_or_default().hires.unwrap_or_default();
+    Ok(encode_mappings(&self._mappings(hires, "generateEncodedMappings")?)?.into_bytes())
+  }
+
+  pub fn write_to<W: io::Write>(&self, writer: &mut W) -> Result<()> {
+    if let Some(code) = &self.render_cache.borrow().code {
+      writer.write_all(code.as_bytes())?;
+      return Ok(());
+    }
+    self._write_with(|s| Ok(writer.write_all(s.as_bytes())?))
+  }
+
+  pub fn write_to_fmt<W: fmt::Write>(&self, writer: &mut W) -> Result<()> {
+    if let Some(code) = &self.render_cache.borrow().code {
+      writer.write_str(code)?;
+      return Ok(());
+    }
+    self._write_with(|s| Ok(writer.write_str(s)?))
+  }
+
+  // streams the code and its encoded mappings in a single traversal
+  pub fn write_with_mappings_to<W: io::Write, M: io::Write>(
+    &self,
+    writer: &mut W,
+    mappings_writer: &mut M,
+    hires: bool,
+  ) -> Result<()> {
+    let mut facade = MappingsFacade::new(hires, &self.sourcemap_locations);
+    let mut encoder = MappingsEncoder::default();
+
+    writer.write_all(self.intro.as_bytes())?;
+    facade.advance(self.intro.as_str());
+    Chunk::each_next(Rc::clone(&self.first_chunk), |chunk| {
+      let chunk = chunk.borrow();
+      writer.write_all(chunk.intro.as_bytes())?;
+      writer.write_all(chunk.content().as_bytes())?;
+      writer.write_all(chunk.outro.as_bytes())?;
+      self._add_chunk_mappings(&mut facade, &chunk, "generate");
+      facade.flush(&mut encoder, mappings_writer)?;
+      Ok(false)
+    })?;
+    writer.write_all(self.outro.as_bytes())?;
+    facade.advance(self.outro.as_str());
+    facade.finish(&mut encoder, mappings_writer)?;
+    Ok(())
+  }
+
+  fn _write_with<F>(&self, mut write: F) -> Result<()>
+  where
+    F: FnMut(&str) -> Result<()>,
+  {
+    write(&self.intro)?;
+    Chunk::each_next(Rc::clone(&self.first_chunk), |chunk| {
+      let chunk = chunk.borrow();
+      write(&chunk.intro)?;
+      write(chunk.content())?;
+      write(&chunk.outro)?;
+      Ok(false)
+    })?;
+    write(&self.outro)
+  }
+
+  fn _render(&self) -> String {
     let mut str = self.intro.clone();
     let _ = Chunk::each_next(Rc::clone(&self.first_chunk), |chunk| {
       str.push_str(chunk.borrow().intro.as_str());
-      str.push_str(chunk.borrow().content.as_str());
+      str.push_str(chunk.borrow().content());
       str.push_str(chunk.borrow().outro.as_str());
       Ok(false)
     });
     str.push_str(self.outro.as_str());
     str
   }
+
+  // visits the generated pieces from the end until `f` returns true, without rendering
+  fn _each_piece_rev<F>(&self, mut f: F)
+  where
+    F: FnMut(&str) -> bool,
+  {
+    if f(&self.outro) {
+      return;
+    }
+    let mut chunk = Some(Rc::clone(&self.last_chunk));
+    while let Some(c) = chunk {
+      let c = c.borrow();
+      if f(&c.outro) || f(c.content()) || f(&c.intro) {
+        return;
+      }
+      chunk = c.previous.clone();
+    }
+    f(&self.intro);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // a fresh string for every check, so nothing is served from the render cache
+  fn edited() -> MagicString {
+    let mut s = MagicString::new("const a = 1;\nconst b = 2;\nexport { a, b }", None);
+    s.overwrite(6, 7, "x", None).unwrap();
+    s.append_left(12, " // a").unwrap();
+    s._move(13, 26, 0).unwrap();
+    s.remove(34, 36).unwrap();
+    s.prepend("/* intro */\n").unwrap();
+    s.append("\n/* outro */").unwrap();
+    s
+  }
+
+  fn map_options(hires: bool) -> Option<GenerateMapOptions> {
+    Some(GenerateMapOptions {
+      hires: Some(hires),
+      ..Default::default()
+    })
+  }
+
+  #[test]
+  fn clones_share_the_source() {
+    let s = edited();
+    let cloned = s._clone();
+    assert!(Arc::ptr_eq(&s.original, &cloned.original));
+    assert_eq!(cloned.to_string(), s.to_string());
+  }
+
+  #[test]
+  fn maps_columns_in_utf16_code_units() {
+    let mut s = MagicString::new("é b\n😀 c", None);
+    s.overwrite(3, 4, "B", None).unwrap();
+    s.overwrite(10, 11, "C", None).unwrap();
+    let mappings = s.generate_map(map_options(true)).unwrap().mappings;
+    // the line break gets its own segment in hires mode
+    assert_eq!(mappings, "AAAA,CAAC,CAAC,CAAC;AACH,EAAE,CAAC");
+  }
+
+  #[test]
+  fn normalizes_line_endings_across_edits() {
+    let mut s = MagicString::new("a\r\nb", None);
+    s.normalize_line_endings(LineEnding::Lf).unwrap();
+    s.normalize_line_endings(LineEnding::CrLf).unwrap();
+    assert_eq!(s.to_string(), "a\r\nb");
+
+    let mut s = MagicString::new("a\r\nb", None);
+    s.overwrite(0, 2, "X\r", None).unwrap();
+    s.normalize_line_endings(LineEnding::Lf).unwrap();
+    assert_eq!(s.to_string(), "X\nb");
+
+    // a lone `\r` starts a new generated line
+    let mut s = MagicString::new("ab", None);
+    s.append_left(1, "\r").unwrap();
+    let mappings = s.generate_map(map_options(true)).unwrap().mappings;
+    assert_eq!(mappings, "AAAA;AAAC");
+  }
+
+  fn reindented(code: &str, from: Option<IndentStyle>, to: IndentStyle) -> String {
+    let mut s = MagicString::new(code, None);
+    s.reindent(from, to, None).unwrap();
+    s.to_string()
+  }
+
+  #[test]
+  fn reindents_only_whole_units_at_line_starts() {
+    let spaces = Some(IndentStyle::Spaces(2));
+    assert_eq!(reindented("     a", spaces, IndentStyle::Tab), "\t\t a");
+    assert_eq!(
+      reindented("\t  a\n  \tb", spaces, IndentStyle::Tab),
+      "\t\ta\n\t\tb"
+    );
+    assert_eq!(
+      reindented("a\tb\n\tc", Some(IndentStyle::Tab), IndentStyle::Spaces(2)),
+      "a\tb\n  c"
+    );
+  }
+
+  #[test]
+  fn reindents_after_any_line_break() {
+    assert_eq!(
+      reindented(
+        "a\r\n\tb\r\tc",
+        Some(IndentStyle::Tab),
+        IndentStyle::Spaces(2)
+      ),
+      "a\r\n  b\r  c"
+    );
+  }
+
+  #[test]
+  fn skips_unindented_strings() {
+    let mut s = MagicString::new("a\nb", None);
+    s.reindent(None, IndentStyle::Tab, None).unwrap();
+    assert_eq!(s.edit_count(), 0);
+    s.reindent(Some(IndentStyle::Tab), IndentStyle::Tab, None)
+      .unwrap();
+    assert_eq!(s.edit_count(), 0);
+  }
+
+  #[test]
+  fn reindents_a_range_given_with_negative_indices() {
+    let mut s = MagicString::new("\ta\n\tb\n\tc", None);
+    s.reindent(
+      Some(IndentStyle::Tab),
+      IndentStyle::Spaces(2),
+      Some(ReindentOptions {
+        start: Some(-4),
+        ..Default::default()
+      }),
+    )
+    .unwrap();
+    assert_eq!(s.to_string(), "\ta\n\tb\n  c");
+  }
+
+  #[test]
+  fn reindents_inserted_text_only_inside_the_range() {
+    let mut s = MagicString::new("a {\n\tb\n}", None);
+    s.overwrite(2, 7, "{\n\t\tx\n\t", None).unwrap();
+    s.reindent(Some(IndentStyle::Tab), IndentStyle::Spaces(2), None)
+      .unwrap();
+    assert_eq!(s.to_string(), "a {\n    x\n  }");
+
+    let mut s = MagicString::new("\ta\n", None);
+    s.prepend("\tx\n").unwrap();
+    s.append("\ty").unwrap();
+    s.reindent(
+      Some(IndentStyle::Tab),
+      IndentStyle::Spaces(2),
+      Some(ReindentOptions {
+        start: Some(0),
+        end: Some(3),
+        ..Default::default()
+      }),
+    )
+    .unwrap();
+    assert_eq!(s.to_string(), "\tx\n  a\n\ty");
+  }
+
+  #[test]
+  fn keeps_tokens_at_their_original_columns() {
+    let mut s = MagicString::new("\ta\n\t\tb", None);
+    s.reindent(Some(IndentStyle::Tab), IndentStyle::Spaces(4), None)
+      .unwrap();
+    assert_eq!(s.to_string(), "    a\n        b");
+    assert_eq!(
+      s.generated_position_for(1).unwrap(),
+      MappedPosition::Exact(4)
+    );
+    assert_eq!(
+      s.generated_position_for(5).unwrap(),
+      MappedPosition::Exact(14)
+    );
+    assert_eq!(
+      s.original_position_for(2).unwrap(),
+      MappedPosition::InsideEdit { start: 0, end: 1 }
+    );
+    let mappings = s.generate_map(map_options(true)).unwrap().mappings;
+    assert_eq!(mappings, "AAAA,IAAC,CAAC;AACF,IAAC,IAAC");
+  }
+
+  fn replace_generated(s: &mut MagicString, pattern: Pattern, replacement: &str, global: bool) {
+    s.replace_with_target(pattern, replacement, global, ReplaceTarget::Generated)
+      .unwrap();
+  }
+
+  fn chunk_parts(s: &MagicString) -> Vec<(u32, u32, String, String, String)> {
+    s.chunks()
+      .map(|c| (c.start, c.end, c.intro, c.content, c.outro))
+      .collect()
+  }
+
+  fn part(
+    start: u32,
+    end: u32,
+    intro: &str,
+    content: &str,
+    outro: &str,
+  ) -> (u32, u32, String, String, String) {
+    (
+      start,
+      end,
+      intro.to_string(),
+      content.to_string(),
+      outro.to_string(),
+    )
+  }
+
+  #[test]
+  fn replaces_generated_matches_spanning_inserted_and_original_text() {
+    let mut s = MagicString::new("let a = b;", None);
+    s.append_left(4, "__").unwrap();
+    replace_generated(&mut s, Pattern::String("__a"), "X", false);
+    assert_eq!(s.to_string(), "let X = b;");
+    // the replacement goes into the inserted text, the original part is emptied
+    assert_eq!(
+      chunk_parts(&s),
+      vec![
+        part(0, 4, "", "let ", "X"),
+        part(4, 5, "", "", ""),
+        part(5, 10, "", " = b;", ""),
+      ]
+    );
+
+    let mut s = MagicString::new("let a = b;", None);
+    s.append_left(5, "__").unwrap();
+    replace_generated(&mut s, Pattern::String("a__ ="), "X", false);
+    assert_eq!(s.to_string(), "let X b;");
+    assert_eq!(
+      chunk_parts(&s),
+      vec![
+        part(0, 4, "", "let ", ""),
+        part(4, 5, "", "X", ""),
+        part(5, 7, "", "", ""),
+        part(7, 10, "", " b;", ""),
+      ]
+    );
+  }
+
+  #[test]
+  fn replaces_generated_matches_inside_and_across_overwrites() {
+    let mut s = MagicString::new("let a = b;", None);
+    s.overwrite(4, 5, "foo", None).unwrap();
+    replace_generated(&mut s, Pattern::String("oo"), "u", false);
+    assert_eq!(s.to_string(), "let fu = b;");
+
+    let mut s = MagicString::new("let a = b;", None);
+    s.overwrite(4, 5, "foo", None).unwrap();
+    replace_generated(&mut s, Pattern::Regex("o+ ="), "$&=", true);
+    assert_eq!(s.to_string(), "let foo == b;");
+    assert_eq!(
+      chunk_parts(&s),
+      vec![
+        part(0, 4, "", "let ", ""),
+        part(4, 5, "", "foo ==", ""),
+        part(5, 7, "", "", ""),
+        part(7, 10, "", " b;", ""),
+      ]
+    );
+  }
+
+  #[test]
+  fn replaces_generated_matches_in_intro_outro_and_at_the_ends() {
+    let mut s = MagicString::new("a", None);
+    s.prepend("/*x*/").unwrap();
+    s.append("//x").unwrap();
+    replace_generated(&mut s, Pattern::Regex("x"), "y", true);
+    assert_eq!(s.to_string(), "/*y*/a//y");
+
+    let mut s = MagicString::new("a", None);
+    replace_generated(&mut s, Pattern::Regex("^"), "<", false);
+    replace_generated(&mut s, Pattern::Regex("$"), ">", false);
+    assert_eq!(s.to_string(), "<a>");
+    assert_eq!(chunk_parts(&s), vec![part(0, 1, "<", "a", "")]);
+  }
+
+  #[test]
+  fn replaces_every_generated_match_once() {
+    let mut s = MagicString::new("let a = b;", None);
+    s.append_left(4, "a").unwrap();
+    replace_generated(&mut s, Pattern::String("a"), "aa", true);
+    assert_eq!(s.to_string(), "let aaaa = b;");
+    let edit_count = s.edit_count();
+    replace_generated(&mut s, Pattern::String("z"), "y", true);
+    assert_eq!(s.edit_count(), edit_count);
+  }
+
+  fn replaced_in_range(start: i32, end: i32, pattern: Pattern) -> Result<String> {
+    let mut s = MagicString::new("aa aa aa", None);
+    s.replace_all_in_range(start, end, pattern, "X")?;
+    Ok(s.to_string())
+  }
+
+  #[test]
+  fn replaces_only_matches_inside_the_range() {
+    let aa = Pattern::String("aa");
+    assert_eq!(replaced_in_range(3, 8, aa).unwrap(), "aa X X");
+    // matches crossing either end are skipped
+    assert_eq!(replaced_in_range(4, 8, aa).unwrap(), "aa aa X");
+    assert_eq!(replaced_in_range(0, 4, aa).unwrap(), "X aa aa");
+    assert_eq!(replaced_in_range(-5, -1, aa).unwrap(), "aa X aa");
+    // lookarounds see the text outside the range
+    assert_eq!(
+      replaced_in_range(3, 8, Pattern::Regex("(?<= )a+")).unwrap(),
+      "aa X X"
+    );
+    assert_eq!(
+      replaced_in_range(3, 8, Pattern::Regex("a(?= )")).unwrap(),
+      "aa aX aa"
+    );
+
+    let mut s = MagicString::new("aa aa aa", None);
+    s.replace_in_range(-2, 8, aa, "X", false).unwrap();
+    assert_eq!(s.to_string(), "aa aa X");
+  }
+
+  #[test]
+  fn rejects_malformed_ranges() {
+    let aa = Pattern::String("aa");
+    let message = |result: Result<String>| result.unwrap_err().err_msg.unwrap_or_default();
+    assert_eq!(
+      message(replaced_in_range(5, 3, aa)),
+      "start must not be greater than end"
+    );
+    assert_eq!(message(replaced_in_range(0, 9, aa)), "end is out of bounds");
+    assert!(matches!(
+      replaced_in_range(0, 8, Pattern::Regex("x*"))
+        .unwrap_err()
+        .err_type,
+      FmsErrType::Range
+    ));
+
+    let mut s = MagicString::new("é é", None);
+    assert!(s
+      .replace_all_in_range(1, 5, Pattern::String("é"), "e")
+      .is_err());
+    assert_eq!(s.edit_count(), 0);
+  }
+
+  #[test]
+  fn replaces_in_overlapping_ranges() {
+    let mut s = MagicString::new("aa aa aa", None);
+    s.replace_all_in_range(0, 5, Pattern::String("aa"), "X")
+      .unwrap();
+    // a match reaching into text replaced before can't be split
+    assert!(s
+      .replace_all_in_range(4, 8, Pattern::String("a a"), "Y")
+      .is_err());
+    s.replace_all_in_range(4, 8, Pattern::String("aa"), "Y")
+      .unwrap();
+    assert_eq!(s.to_string(), "X X Y");
+  }
+
+  #[test]
+  fn dedents_inserted_text_like_the_original() {
+    let mut s = MagicString::new("  a\n  b", None);
+    s.append_left(3, "\n    x").unwrap();
+    s.prepend_left(6, ">>").unwrap();
+    s.dedent(Some(2), None).unwrap();
+    assert_eq!(s.to_string(), "a\n  x\n>>b");
+
+    let mut s = MagicString::new("  a\n  b", None);
+    s.append_left(2, "  ").unwrap();
+    s.dedent(Some(2), None).unwrap();
+    assert_eq!(s.to_string(), "  a\nb");
+  }
+
+  #[test]
+  fn dedents_after_a_lone_carriage_return() {
+    let mut s = MagicString::new("    a\r    b\r\n    c", None);
+    s.dedent(None, None).unwrap();
+    assert_eq!(s.to_string(), "a\rb\r\nc");
+  }
+
+  #[test]
+  fn expands_shorthand_only_in_object_literals() {
+    let rename = |code: &str| {
+      let mut s = MagicString::new(code, None);
+      s.replace_identifiers(&[("foo", "bar")], None).unwrap();
+      s.to_string()
+    };
+    assert_eq!(rename("const { foo } = o"), "const { foo: bar } = o");
+    assert_eq!(rename("f({ a, foo })"), "f({ a, foo: bar })");
+    assert_eq!(rename("`${foo}`"), "`${bar}`");
+    assert_eq!(rename("<a>{foo}</a>"), "<a>{bar}</a>");
+    assert_eq!(rename("<a b=\"c\" d={foo} />"), "<a b=\"c\" d={bar} />");
+    assert_eq!(rename("<a b={{ foo }} />"), "<a b={{ foo: bar }} />");
+    assert_eq!(rename("if (x) { foo }"), "if (x) { bar }");
+    assert_eq!(rename("() => { foo }"), "() => { bar }");
+    assert_eq!(rename("{ { foo } }"), "{ { bar } }");
+    assert_eq!(rename("case 1: { foo }"), "case 1: { bar }");
+    assert_eq!(rename("x = a ? { foo } : 1"), "x = a ? { foo: bar } : 1");
+    assert_eq!(
+      rename("export default { foo }"),
+      "export default { foo: bar }"
+    );
+  }
+
+  #[test]
+  fn replaces_at_match_positions_with_an_offset() {
+    let options = || {
+      Some(MagicStringOptions {
+        offset: Some(2),
+        ..Default::default()
+      })
+    };
+    let mut s = MagicString::new("xxabcabc", options());
+    s.replace_by_string("abc", "X").unwrap();
+    assert_eq!(s.to_string(), "xxXabc");
+
+    let mut s = MagicString::new("xxabcabc", options());
+    s.replace_by_regexp("abc", "X", false).unwrap();
+    assert_eq!(s.to_string(), "xxXabc");
+
+    let mut s = MagicString::new("xxabcabc", options());
+    s.replace_by_regexp("b(c)", "[$1]", true).unwrap();
+    assert_eq!(s.to_string(), "xxa[c]a[c]");
+  }
+
+  #[test]
+  fn addresses_columns_in_utf16_code_units() {
+    let mut s = MagicString::new("é = 1\n😀 = 2", None);
+    assert_eq!(s.index_of(0, 1).unwrap(), 2);
+    assert_eq!(s.index_of(1, 2).unwrap(), 11);
+    assert_eq!(s.position_of(11).unwrap(), (1, 2));
+    assert!(s.position_of(1).is_err());
+    // between the halves of the surrogate pair
+    assert!(matches!(
+      s.index_of(1, 1).unwrap_err().err_type,
+      FmsErrType::Range
+    ));
+    assert!(s.slice_at((1, 1), (1, 3)).is_err());
+    assert!(s.index_of(0, 6).is_err());
+    assert!(s.slice(1, 3).is_err());
+    assert!(s.append_left(1, "x").is_err());
+
+    assert_eq!(s.slice_at((0, 0), (0, 1)).unwrap(), "é");
+    s.overwrite_at((1, 0), (1, 2), "x", None).unwrap();
+    assert_eq!(s.to_string(), "é = 1\nx = 2");
+  }
+
+  #[test]
+  fn streams_the_rendered_string() {
+    let expected = edited().to_string();
+
+    let mut bytes = vec![];
+    edited().write_to(&mut bytes).unwrap();
+    assert_eq!(String::from_utf8(bytes).unwrap(), expected);
+
+    let mut string = String::new();
+    edited().write_to_fmt(&mut string).unwrap();
+    assert_eq!(string, expected);
+  }
+
+  #[test]
+  fn streams_the_encoded_mappings() {
+    for hires in [false, true] {
+      let expected = edited().generate_map(map_options(hires)).unwrap();
+
+      let (mut code, mut mappings) = (vec![], vec![]);
+      edited()
+        .write_with_mappings_to(&mut code, &mut mappings, hires)
+        .unwrap();
+      assert_eq!(String::from_utf8(code).unwrap(), edited().to_string());
+      assert_eq!(String::from_utf8(mappings).unwrap(), expected.mappings);
+    }
+  }
 }
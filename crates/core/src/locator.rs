@@ -1,19 +1,51 @@
 pub struct Locator {
   line_offsets: Vec<usize>,
+  // end of each line, excluding its line break
+  line_ends: Vec<usize>,
+  // lines without multi-byte characters, their byte and UTF-16 columns are the same
+  ascii_lines: Vec<bool>,
 }
 
 impl Locator {
+  // `\r\n`, `\r` and `\n` all end a line
   pub fn new(source: &str) -> Self {
-    let original_lines = source.lines();
-    let mut line_offsets = Vec::new();
+    let bytes = source.as_bytes();
+    let mut line_offsets = vec![0];
+    let mut line_ends = vec![];
+    let mut ascii_lines = vec![];
+    let mut ascii = true;
     let mut pos = 0;
 
-    for line in original_lines {
-      line_offsets.push(pos);
-      pos += line.chars().count() + 1;
+    while pos < bytes.len() {
+      match bytes[pos] {
+        b'\r' if bytes.get(pos + 1) == Some(&b'\n') => {
+          line_ends.push(pos);
+          ascii_lines.push(ascii);
+          ascii = true;
+          pos += 2;
+          line_offsets.push(pos);
+        }
+        b'\r' | b'\n' => {
+          line_ends.push(pos);
+          ascii_lines.push(ascii);
+          ascii = true;
+          pos += 1;
+          line_offsets.push(pos);
+        }
+        byte => {
+          ascii &= byte.is_ascii();
+          pos += 1
+        }
+      }
     }
+    line_ends.push(bytes.len());
+    ascii_lines.push(ascii);
 
-    Locator { line_offsets }
+    Locator {
+      line_offsets,
+      line_ends,
+      ascii_lines,
+    }
   }
 
   pub fn locate(&self, index: usize) -> Option<(usize, usize)> {
@@ -37,4 +69,32 @@ impl Locator {
     let column = index - self.line_offsets[line];
     Some((line, column))
   }
+
+  // like `locate`, but counts the column in UTF-16 code units as source maps do
+  pub fn locate_utf16(&self, source: &str, index: usize) -> Option<(usize, usize)> {
+    let (line, column) = self.locate(index)?;
+    if self.ascii_lines[line] {
+      return Some((line, column));
+    }
+    Some((line, source[index - column..index].encode_utf16().count()))
+  }
+
+  // inverse of `locate_utf16`, `None` when the column is past the end of the line or
+  // between the two halves of a surrogate pair
+  pub fn index_of_utf16(&self, source: &str, line: usize, column: usize) -> Option<usize> {
+    let line_start = *self.line_offsets.get(line)?;
+    let line_end = self.line_ends[line];
+
+    if self.ascii_lines[line] {
+      return (line_start + column <= line_end).then_some(line_start + column);
+    }
+    let mut units = 0;
+    for (i, c) in source[line_start..line_end].char_indices() {
+      if units >= column {
+        return (units == column).then_some(line_start + i);
+      }
+      units += c.len_utf16();
+    }
+    (units == column).then_some(line_end)
+  }
 }
@@ -0,0 +1,278 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::chunk::Chunk;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MappedPosition {
+  // the character is kept as is
+  Exact(u32),
+  // the character belongs to an overwritten or inserted region, `start..end` is that region
+  // on the other side (zero-length for inserted content)
+  InsideEdit { start: u32, end: u32 },
+  // the character was removed, `at` is where it would have been
+  Removed { at: u32 },
+}
+
+struct Segment {
+  start: u32,
+  end: u32,
+  generated_start: u32,
+  intro_len: u32,
+  content_len: u32,
+  outro_len: u32,
+  edited: bool,
+}
+
+impl Segment {
+  fn content_start(&self) -> u32 {
+    self.generated_start + self.intro_len
+  }
+
+  fn content_end(&self) -> u32 {
+    self.content_start() + self.content_len
+  }
+
+  fn generated_end(&self) -> u32 {
+    self.content_end() + self.outro_len
+  }
+}
+
+// prefix lengths of the chunk list, in generated order
+pub struct PositionIndex {
+  intro_len: u32,
+  outro_len: u32,
+  segments: Vec<Segment>,
+  // indices into `segments`, sorted by original start
+  by_original: Vec<usize>,
+}
+
+impl PositionIndex {
+  pub fn new(intro: &str, first_chunk: Rc<RefCell<Chunk>>, outro: &str) -> Self {
+    let mut segments = vec![];
+    let mut generated_start = intro.len() as u32;
+    let _ = Chunk::each_next(first_chunk, |chunk| {
+      let chunk = chunk.borrow();
+      let segment = Segment {
+        start: chunk.start,
+        end: chunk.end,
+        generated_start,
+        intro_len: chunk.intro.len() as u32,
+        content_len: chunk.content().len() as u32,
+        outro_len: chunk.outro.len() as u32,
+        edited: chunk.is_edited(),
+      };
+      generated_start = segment.generated_end();
+      segments.push(segment);
+      Ok(false)
+    });
+    let mut by_original: Vec<usize> = (0..segments.len()).collect();
+    by_original.sort_by_key(|&i| segments[i].start);
+
+    Self {
+      intro_len: intro.len() as u32,
+      outro_len: outro.len() as u32,
+      segments,
+      by_original,
+    }
+  }
+
+  pub fn len(&self) -> u32 {
+    self
+      .segments
+      .last()
+      .map_or(self.intro_len, |s| s.generated_end())
+      + self.outro_len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn generated_position_for(&self, index: u32) -> MappedPosition {
+    let i = self
+      .by_original
+      .partition_point(|&i| self.segments[i].end <= index);
+    match self.by_original.get(i).map(|&i| &self.segments[i]) {
+      Some(s) if !s.edited => MappedPosition::Exact(s.content_start() + index - s.start),
+      Some(s) if s.content_len > 0 => MappedPosition::InsideEdit {
+        start: s.content_start(),
+        end: s.content_end(),
+      },
+      Some(s) => MappedPosition::Removed {
+        at: s.content_start(),
+      },
+      // `index` is the end of the original string
+      None => match self.by_original.last().map(|&i| &self.segments[i]) {
+        Some(s) if !s.edited => MappedPosition::Exact(s.content_end()),
+        Some(s) if s.content_len > 0 => MappedPosition::InsideEdit {
+          start: s.content_start(),
+          end: s.content_end(),
+        },
+        Some(s) => MappedPosition::Removed {
+          at: s.content_end(),
+        },
+        None => MappedPosition::Exact(self.intro_len),
+      },
+    }
+  }
+
+  pub fn original_position_for(&self, index: u32) -> MappedPosition {
+    let first_start = self.segments.first().map_or(0, |s| s.start);
+    let last_end = self.segments.last().map_or(0, |s| s.end);
+    if index < self.intro_len {
+      return MappedPosition::InsideEdit {
+        start: first_start,
+        end: first_start,
+      };
+    }
+
+    let i = self
+      .segments
+      .partition_point(|s| s.generated_end() <= index);
+    match self.segments.get(i) {
+      Some(s) if index < s.content_start() => MappedPosition::InsideEdit {
+        start: s.start,
+        end: s.start,
+      },
+      Some(s) if index >= s.content_end() => MappedPosition::InsideEdit {
+        start: s.end,
+        end: s.end,
+      },
+      Some(s) if !s.edited => MappedPosition::Exact(s.start + index - s.content_start()),
+      Some(s) => MappedPosition::InsideEdit {
+        start: s.start,
+        end: s.end,
+      },
+      // `index` is inside the outro or at the end of the generated string
+      None => match self.segments.last() {
+        Some(s) if index == self.len() && self.outro_len == 0 && s.outro_len == 0 && !s.edited => {
+          MappedPosition::Exact(s.end)
+        }
+        _ => MappedPosition::InsideEdit {
+          start: last_end,
+          end: last_end,
+        },
+      },
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::MappedPosition::*;
+  use crate::{error::FmsErrType, MagicString};
+
+  // >>abXYZefij
+  fn edited() -> MagicString {
+    let mut s = MagicString::new("abcdefghij", None);
+    s.prepend(">>").unwrap();
+    s.overwrite(2, 4, "XYZ", None).unwrap();
+    s.remove(6, 8).unwrap();
+    s
+  }
+
+  #[test]
+  fn maps_original_indices_through_edits() {
+    let s = edited();
+    let positions: Vec<_> = [0, 2, 3, 4, 6, 7, 10]
+      .into_iter()
+      .map(|i| s.generated_position_for(i).unwrap())
+      .collect();
+    assert_eq!(
+      positions,
+      vec![
+        Exact(2),
+        InsideEdit { start: 4, end: 7 },
+        InsideEdit { start: 4, end: 7 },
+        Exact(7),
+        Removed { at: 9 },
+        Removed { at: 9 },
+        Exact(11),
+      ]
+    );
+  }
+
+  #[test]
+  fn maps_generated_indices_through_edits() {
+    let s = edited();
+    let positions: Vec<_> = [0, 1, 2, 5, 7, 9, 11]
+      .into_iter()
+      .map(|i| s.original_position_for(i).unwrap())
+      .collect();
+    assert_eq!(
+      positions,
+      vec![
+        InsideEdit { start: 0, end: 0 },
+        InsideEdit { start: 0, end: 0 },
+        Exact(0),
+        InsideEdit { start: 2, end: 4 },
+        Exact(4),
+        Exact(8),
+        Exact(10),
+      ]
+    );
+  }
+
+  #[test]
+  fn maps_moved_content() {
+    let mut s = MagicString::new("abcdef", None);
+    s._move(0, 2, 6).unwrap();
+    assert_eq!(s.to_string(), "cdefab");
+    assert_eq!(s.generated_position_for(0).unwrap(), Exact(4));
+    assert_eq!(s.generated_position_for(2).unwrap(), Exact(0));
+    assert_eq!(s.original_position_for(5).unwrap(), Exact(1));
+  }
+
+  #[test]
+  fn maps_content_removed_at_the_end() {
+    let mut s = MagicString::new("abc", None);
+    s.remove(1, 3).unwrap();
+    assert_eq!(s.generated_position_for(2).unwrap(), Removed { at: 1 });
+    assert_eq!(s.generated_position_for(3).unwrap(), Removed { at: 1 });
+  }
+
+  #[test]
+  fn maps_inserted_content_to_an_empty_range() {
+    let mut s = MagicString::new("abc", None);
+    s.append_left(1, "__").unwrap();
+    s.append("!").unwrap();
+    assert_eq!(
+      s.original_position_for(1).unwrap(),
+      InsideEdit { start: 1, end: 1 }
+    );
+    assert_eq!(
+      s.original_position_for(2).unwrap(),
+      InsideEdit { start: 1, end: 1 }
+    );
+    assert_eq!(s.original_position_for(3).unwrap(), Exact(1));
+    assert_eq!(
+      s.original_position_for(5).unwrap(),
+      InsideEdit { start: 3, end: 3 }
+    );
+    assert_eq!(
+      s.original_position_for(6).unwrap(),
+      InsideEdit { start: 3, end: 3 }
+    );
+  }
+
+  #[test]
+  fn rebuilds_the_index_after_an_edit() {
+    let mut s = MagicString::new("abc", None);
+    assert_eq!(s.generated_position_for(2).unwrap(), Exact(2));
+    s.prepend("__").unwrap();
+    assert_eq!(s.generated_position_for(2).unwrap(), Exact(4));
+  }
+
+  #[test]
+  fn rejects_indices_past_the_end() {
+    let s = edited();
+    assert!(matches!(
+      s.generated_position_for(11).unwrap_err().err_type,
+      FmsErrType::Range
+    ));
+    assert!(matches!(
+      s.original_position_for(12).unwrap_err().err_type,
+      FmsErrType::Range
+    ));
+  }
+}
@@ -0,0 +1,93 @@
+use std::{
+  collections::HashMap,
+  sync::{LazyLock, Mutex},
+};
+
+use fms_regexp::CompiledRegex;
+
+use crate::result::Result;
+
+const CAPACITY: usize = 128;
+
+// shared by all instances, so the same pattern used across modules compiles once
+static REGEX_CACHE: LazyLock<Mutex<RegexCache>> =
+  LazyLock::new(|| Mutex::new(RegexCache::new(CAPACITY)));
+
+pub fn cached_regex(pattern: &str) -> Result<CompiledRegex> {
+  let mut cache = REGEX_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+  cache.get_or_compile(pattern)
+}
+
+// least recently used entries are evicted once `capacity` is reached
+pub struct RegexCache {
+  capacity: usize,
+  tick: u64,
+  entries: HashMap<String, (CompiledRegex, u64)>,
+}
+
+impl RegexCache {
+  pub fn new(capacity: usize) -> Self {
+    Self {
+      capacity,
+      tick: 0,
+      entries: HashMap::with_capacity(capacity),
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  pub fn get_or_compile(&mut self, pattern: &str) -> Result<CompiledRegex> {
+    self.tick += 1;
+    if let Some((regex, last_used)) = self.entries.get_mut(pattern) {
+      *last_used = self.tick;
+      // both engines are reference counted, cloning does not recompile
+      return Ok(regex.clone());
+    }
+
+    let regex = CompiledRegex::new(pattern)?;
+    if self.entries.len() >= self.capacity {
+      let oldest = self
+        .entries
+        .iter()
+        .min_by_key(|(_, (_, last_used))| *last_used)
+        .map(|(pattern, _)| pattern.clone());
+      if let Some(oldest) = oldest {
+        self.entries.remove(&oldest);
+      }
+    }
+    self
+      .entries
+      .insert(pattern.to_string(), (regex.clone(), self.tick));
+    Ok(regex)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn evicts_least_recently_used() {
+    let mut cache = RegexCache::new(2);
+    cache.get_or_compile("a").unwrap();
+    cache.get_or_compile("b").unwrap();
+    cache.get_or_compile("a").unwrap();
+    cache.get_or_compile("c").unwrap();
+    assert_eq!(cache.len(), 2);
+    assert!(cache.entries.contains_key("a"));
+    assert!(!cache.entries.contains_key("b"));
+  }
+
+  #[test]
+  fn reports_invalid_patterns() {
+    let mut cache = RegexCache::new(2);
+    assert!(cache.get_or_compile("(").is_err());
+    assert!(cache.is_empty());
+  }
+}
@@ -0,0 +1,90 @@
+use fms_regexp::Captures;
+
+enum Part {
+  Literal(String),
+  // `$&`
+  Match,
+  // `$n`, `$nn` and `$<name>`
+  Group(usize),
+}
+
+// a replacement string parsed once per `replace` call instead of once per match, following
+// `String.prototype.replace`: references to groups the pattern does not have stay verbatim
+pub struct ReplacementTemplate {
+  parts: Vec<Part>,
+}
+
+impl ReplacementTemplate {
+  // `names` has one entry per group of the pattern, including the whole match
+  pub fn parse(replacement: &str, names: &[Option<String>]) -> Self {
+    let groups = names.len().saturating_sub(1);
+    let has_names = names.iter().any(Option::is_some);
+    let mut parts = vec![];
+    let mut literal = String::new();
+    let mut rest = replacement;
+
+    while let Some(i) = rest.find('$') {
+      literal.push_str(&rest[..i]);
+      let after = &rest[i + 1..];
+      let digit = |at: usize| {
+        after
+          .as_bytes()
+          .get(at)
+          .filter(|b| b.is_ascii_digit())
+          .map(|b| (b - b'0') as usize)
+      };
+      let (part, consumed) = if after.starts_with('$') {
+        literal.push('$');
+        (None, 1)
+      } else if after.starts_with('&') {
+        (Some(Part::Match), 1)
+      } else if let Some(first) = digit(0) {
+        // `$10` is group 10 when it exists and group 1 followed by `0` otherwise
+        match digit(1).map(|second| first * 10 + second) {
+          Some(n) if (1..=groups).contains(&n) => (Some(Part::Group(n)), 2),
+          _ if (1..=groups).contains(&first) => (Some(Part::Group(first)), 1),
+          _ => {
+            literal.push('$');
+            (None, 0)
+          }
+        }
+      } else if let Some(end) = after
+        .find('>')
+        .filter(|_| has_names && after.starts_with('<'))
+      {
+        // an unknown name expands to nothing, like an unmatched group
+        let name = &after[1..end];
+        let group = names.iter().position(|n| n.as_deref() == Some(name));
+        (group.map(Part::Group), end + 1)
+      } else {
+        literal.push('$');
+        (None, 0)
+      };
+      if let Some(part) = part {
+        if !literal.is_empty() {
+          parts.push(Part::Literal(std::mem::take(&mut literal)));
+        }
+        parts.push(part);
+      }
+      rest = &after[consumed..];
+    }
+    literal.push_str(rest);
+    if !literal.is_empty() {
+      parts.push(Part::Literal(literal));
+    }
+
+    Self { parts }
+  }
+
+  pub fn expand(&self, caps: &Captures) -> String {
+    let mut expanded = String::new();
+    for part in &self.parts {
+      match part {
+        Part::Literal(s) => expanded.push_str(s),
+        Part::Match => expanded.push_str(&caps[0]),
+        Part::Group(n) => expanded.push_str(caps.get(*n).map_or("", |m| m.as_str())),
+      }
+    }
+    expanded
+  }
+}
@@ -0,0 +1,578 @@
+use aho_corasick::{AhoCorasick, MatchKind};
+use fms_regexp::{Captures, CompiledRegex, Match};
+
+use crate::{
+  error::{Error, FmsErrType},
+  position::{MappedPosition, PositionIndex},
+  regex_cache::cached_regex,
+  result::Result,
+  utils::{byte_to_utf16_index, utf16_to_byte_index},
+};
+
+#[derive(Clone, Copy)]
+pub enum Pattern<'a> {
+  String(&'a str),
+  Regex(&'a str),
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReplaceTarget {
+  // match against the original string, matches may not span edited chunks
+  #[default]
+  Original,
+  // match against the current output, inserted content is edited in place
+  Generated,
+}
+
+#[derive(Default)]
+pub struct ReplaceManyOptions {
+  // only match when not surrounded by identifier characters
+  pub word_boundary: Option<bool>,
+}
+
+#[derive(Default)]
+pub struct IdentifierOptions {
+  // regex character class of the characters identifiers are made of,
+  // defaults to alphanumerics, `_` and `$`
+  pub identifier_chars: Option<String>,
+  // skip `obj.name`, defaults to true
+  pub skip_member_access: Option<bool>,
+  // skip `{ name: value }`, defaults to true, shorthand `{ name }` then keeps its key
+  pub skip_property_keys: Option<bool>,
+}
+
+#[derive(Default)]
+pub struct FindOptions {
+  // search the current output instead of the original string
+  pub generated: Option<bool>,
+}
+
+// the `lastIndex` handling of a JS RegExp, see `RegExpBuiltinExec`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RegexState {
+  pub global: bool,
+  pub sticky: bool,
+  // where a sticky regexp matches, written back after matching like `lastIndex`, in UTF-16
+  // code units of the searched text
+  pub last_index: u32,
+}
+
+impl RegexState {
+  pub fn new(global: bool) -> Self {
+    Self {
+      global,
+      ..Default::default()
+    }
+  }
+
+  // the matches `String.prototype.replace` would replace, `last_index` ends up where it
+  // would in JS
+  pub fn captures<'h>(
+    &mut self,
+    regexp: &CompiledRegex,
+    text: &'h str,
+  ) -> Result<Vec<Captures<'h>>> {
+    if self.global {
+      // starts over and ends at 0 once a match fails
+      self.last_index = 0;
+      return Ok(if self.sticky {
+        regexp.captures_all_sticky(text, 0, true)?
+      } else {
+        regexp.captures_all(text, 0, true)?
+      });
+    }
+    if !self.sticky {
+      return Ok(regexp.captures_all(text, 0, false)?);
+    }
+
+    let start = utf16_to_byte_index(text, self.last_index as usize);
+    let found = if start > text.len() {
+      None
+    } else {
+      regexp.captures_sticky_at(text, start)?
+    };
+    self.last_index = found.as_ref().map_or(0, |caps| {
+      byte_to_utf16_index(text, caps.get(0).unwrap().end()) as u32
+    });
+    Ok(found.into_iter().collect())
+  }
+}
+
+// `start` and `end` are original indices, `None` when the text has no original counterpart
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchRange {
+  pub start: Option<u32>,
+  pub end: Option<u32>,
+  pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FindMatch {
+  pub start: Option<u32>,
+  pub end: Option<u32>,
+  pub text: String,
+  // capture groups `1..`, `None` for groups that did not participate
+  pub captures: Vec<Option<MatchRange>>,
+}
+
+impl FindMatch {
+  // `position_index` maps generated indices back, pass `None` when `caps` ran on the original
+  pub fn from_captures(caps: &Captures, position_index: Option<&PositionIndex>) -> Self {
+    let whole = to_range(caps.get(0).unwrap(), position_index);
+    FindMatch {
+      start: whole.start,
+      end: whole.end,
+      text: whole.text,
+      captures: caps
+        .iter()
+        .skip(1)
+        .map(|m| m.map(|m| to_range(m, position_index)))
+        .collect(),
+    }
+  }
+}
+
+// the result of `match_all`, `names` has one entry per capture group `1..`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchAll {
+  pub names: Vec<Option<String>>,
+  pub matches: Vec<FindMatch>,
+}
+
+fn to_range(m: Match, position_index: Option<&PositionIndex>) -> MatchRange {
+  let (start, end) = match position_index {
+    None => (Some(m.start() as u32), Some(m.end() as u32)),
+    Some(index) => {
+      let exact = |i: usize| match index.original_position_for(i as u32) {
+        MappedPosition::Exact(i) => Some(i),
+        _ => None,
+      };
+      let start = exact(m.start());
+      // the end is exclusive, map the last character instead
+      let end = if m.is_empty() {
+        start
+      } else {
+        exact(m.end() - 1).map(|i| i + 1)
+      };
+      (start, end)
+    }
+  };
+  MatchRange {
+    start,
+    end,
+    text: m.as_str().to_string(),
+  }
+}
+
+fn is_identifier_char(c: char) -> bool {
+  c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+fn at_word_boundary(
+  text: &str,
+  start: usize,
+  end: usize,
+  is_identifier_char: &dyn Fn(char) -> bool,
+) -> bool {
+  !text[..start]
+    .chars()
+    .next_back()
+    .is_some_and(is_identifier_char)
+    && !text[end..].chars().next().is_some_and(is_identifier_char)
+}
+
+// `obj.foo` or `obj?.foo`, but not `...foo`
+fn is_member_access(text: &str, start: usize) -> bool {
+  let before = text[..start].trim_end();
+  before.ends_with('.') && !before.ends_with("...")
+}
+
+// `{ foo: 1 }` or `, foo: 1`, a heuristic that leaves `a ? foo : bar` alone
+fn is_property_key(text: &str, start: usize, end: usize) -> bool {
+  let before = text[..start].trim_end();
+  let after = text[end..].trim_start();
+  (before.ends_with('{') || before.ends_with(',')) && after.starts_with(':')
+}
+
+// what a renamed identifier also names, so renaming it does not change that name
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Shorthand {
+  // `{ foo }`, becomes `{ foo: bar }`
+  Property,
+  // `import { foo } from`, becomes `import { foo as bar } from`
+  Import,
+  // `export { foo }`, becomes `export { bar as foo }`
+  Export,
+  // `export { foo } from` or `import { foo as baz } from`, a binding of another module
+  Foreign,
+}
+
+// a heuristic like `is_property_key`, brackets inside strings and comments are not skipped,
+// `Property` only applies in braces that open an object literal or a destructuring pattern
+pub fn shorthand(text: &str, start: usize, end: usize) -> Option<Shorthand> {
+  let before = text[..start].trim_end();
+  let after = text[end..].trim_start();
+  let is_as = starts_with_word(after, "as");
+  if !(before.ends_with('{') || before.ends_with(','))
+    || !(after.starts_with('}')
+      || after.starts_with(',')
+      // `{ foo = 1 } = obj`
+      || (after.starts_with('=') && !after.starts_with("==") && !after.starts_with("=>"))
+      || is_as)
+  {
+    return None;
+  }
+  let open = enclosing_brace(text, start)?;
+  let close = closing_brace(text, end)?;
+  let is_export = ends_with_word(text[..open].trim_end(), "export");
+  let is_from = starts_with_word(text[close + 1..].trim_start(), "from");
+  match (is_export, is_from, is_as) {
+    (true, true, _) | (false, true, true) => Some(Shorthand::Foreign),
+    (true, false, false) => Some(Shorthand::Export),
+    (false, true, false) => Some(Shorthand::Import),
+    // `export { foo as baz }` or `{ foo as baz }`, only `foo` is a local name
+    (_, false, true) => None,
+    (false, false, false) if brace_kind(text, open) == Brace::Object => Some(Shorthand::Property),
+    (false, false, false) => None,
+  }
+}
+
+#[derive(PartialEq, Eq)]
+enum Brace {
+  // an object literal or a destructuring pattern
+  Object,
+  // a block, a class or function body, or anything else that is not an expression
+  Block,
+  // `${ }` in a template or `{ }` around a JSX child or attribute value
+  Expression,
+}
+
+// what the `{` at `open` starts, judged by the token before it
+fn brace_kind(text: &str, open: usize) -> Brace {
+  if text[..open].ends_with('$') {
+    return Brace::Expression;
+  }
+  let before = text[..open].trim_end();
+  let Some(last) = before.chars().next_back() else {
+    return Brace::Block;
+  };
+  let rest = &before[..before.len() - last.len_utf8()];
+  match last {
+    '>' if rest.ends_with('=') => Brace::Block,
+    // `<div>{foo}</div>`
+    '>' => Brace::Expression,
+    '=' if is_jsx_attribute(rest) => Brace::Expression,
+    '(' | '[' | ',' | '=' | '?' | '!' | '&' | '|' | '+' | '-' | '*' | '%' | '^' | '~' => {
+      Brace::Object
+    }
+    '.' if rest.ends_with("..") => Brace::Object,
+    ':' if is_case_label(rest) => Brace::Block,
+    ':' => Brace::Object,
+    // `${ { foo } }` or `<a b={{ foo }} />`, but `{ { foo } }` is a nested block
+    '{' => match brace_kind(text, rest.len()) {
+      Brace::Expression => Brace::Object,
+      _ => Brace::Block,
+    },
+    c if is_identifier_char(c) => {
+      let is_operand = [
+        "return", "yield", "await", "typeof", "void", "delete", "in", "of", "case", "throw",
+        "default", "const", "let", "var",
+      ]
+      .iter()
+      .any(|keyword| ends_with_word(before, keyword));
+      if is_operand {
+        Brace::Object
+      } else {
+        Brace::Block
+      }
+    }
+    _ => Brace::Block,
+  }
+}
+
+// `<a b={`, the attribute name follows the tag name, another attribute or its value
+fn is_jsx_attribute(before_eq: &str) -> bool {
+  let before_eq = before_eq.trim_end();
+  let before_name =
+    before_eq.trim_end_matches(|c: char| is_identifier_char(c) || c == '-' || c == ':');
+  if before_name.len() == before_eq.len() || !before_name.ends_with(char::is_whitespace) {
+    return false;
+  }
+  let before_name = before_name.trim_end();
+  match before_name.chars().next_back() {
+    Some('"' | '\'' | '}') => true,
+    Some(c) if is_identifier_char(c) => !["const", "let", "var"]
+      .iter()
+      .any(|keyword| ends_with_word(before_name, keyword)),
+    _ => false,
+  }
+}
+
+// `case 1:` or `default:` in a `switch`
+fn is_case_label(before_colon: &str) -> bool {
+  let statement = before_colon
+    .rfind([';', '{', '}', '\n'])
+    .map_or(before_colon, |i| &before_colon[i + 1..])
+    .trim();
+  starts_with_word(statement, "case") || statement == "default"
+}
+
+fn starts_with_word(text: &str, word: &str) -> bool {
+  text
+    .strip_prefix(word)
+    .is_some_and(|rest| !rest.chars().next().is_some_and(is_identifier_char))
+}
+
+fn ends_with_word(text: &str, word: &str) -> bool {
+  text
+    .strip_suffix(word)
+    .is_some_and(|rest| !rest.chars().next_back().is_some_and(is_identifier_char))
+}
+
+// the `{` of the innermost bracket pair around `index`, `None` for `(` and `[`
+fn enclosing_brace(text: &str, index: usize) -> Option<usize> {
+  let mut depth = 0;
+  for (i, byte) in text.as_bytes()[..index].iter().enumerate().rev() {
+    match byte {
+      b')' | b']' | b'}' => depth += 1,
+      b'(' | b'[' | b'{' if depth > 0 => depth -= 1,
+      b'{' => return Some(i),
+      b'(' | b'[' => return None,
+      _ => {}
+    }
+  }
+  None
+}
+
+fn closing_brace(text: &str, index: usize) -> Option<usize> {
+  let mut depth = 0;
+  for (i, byte) in text.as_bytes().iter().enumerate().skip(index) {
+    match byte {
+      b'(' | b'[' | b'{' => depth += 1,
+      b')' | b']' | b'}' if depth > 0 => depth -= 1,
+      b'}' => return Some(i),
+      b')' | b']' => return None,
+      _ => {}
+    }
+  }
+  None
+}
+
+// leftmost-longest, non-overlapping matches of all `patterns` as (start, end, pattern index),
+// empty patterns never match and `accept` can reject a match by its range
+pub fn find_many(
+  text: &str,
+  patterns: &[&str],
+  accept: Option<&dyn Fn(usize, usize) -> bool>,
+) -> Result<Vec<(usize, usize, usize)>> {
+  let ids: Vec<usize> = (0..patterns.len())
+    .filter(|&i| !patterns[i].is_empty())
+    .collect();
+  let build = |kind: MatchKind| {
+    AhoCorasick::builder()
+      .match_kind(kind)
+      .build(ids.iter().map(|&i| patterns[i]))
+      .map_err(|e| Error::from_reason(FmsErrType::Type, e.to_string().as_str()))
+  };
+
+  let Some(accept) = accept else {
+    return Ok(
+      build(MatchKind::LeftmostLongest)?
+        .find_iter(text)
+        .map(|m| (m.start(), m.end(), ids[m.pattern().as_usize()]))
+        .collect(),
+    );
+  };
+
+  // the longest match at a position may be rejected while a shorter one is accepted,
+  // so pick among all matches
+  let mut candidates: Vec<(usize, usize, usize)> = build(MatchKind::Standard)?
+    .find_overlapping_iter(text)
+    .filter(|m| accept(m.start(), m.end()))
+    .map(|m| (m.start(), m.end(), ids[m.pattern().as_usize()]))
+    .collect();
+  candidates.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)).then(a.2.cmp(&b.2)));
+
+  let mut matches = vec![];
+  let mut last_end = 0;
+  for (start, end, id) in candidates {
+    if start >= last_end {
+      matches.push((start, end, id));
+      last_end = end;
+    }
+  }
+  Ok(matches)
+}
+
+pub fn find_words(text: &str, patterns: &[&str]) -> Result<Vec<(usize, usize, usize)>> {
+  find_many(
+    text,
+    patterns,
+    Some(&|start, end| at_word_boundary(text, start, end, &is_identifier_char)),
+  )
+}
+
+// whole identifiers only, see `IdentifierOptions`
+pub fn find_identifiers(
+  text: &str,
+  names: &[&str],
+  options: &IdentifierOptions,
+) -> Result<Vec<(usize, usize, usize)>> {
+  let identifier_chars = match &options.identifier_chars {
+    Some(class) => Some(cached_regex(&concat_string!("^(?:", class, ")$"))?),
+    None => None,
+  };
+  let is_identifier_char = |c: char| match &identifier_chars {
+    Some(re) => re.is_match(c.encode_utf8(&mut [0; 4])).unwrap_or(false),
+    None => is_identifier_char(c),
+  };
+  let skip_member_access = options.skip_member_access.unwrap_or(true);
+  let skip_property_keys = options.skip_property_keys.unwrap_or(true);
+
+  find_many(
+    text,
+    names,
+    Some(&|start, end| {
+      at_word_boundary(text, start, end, &is_identifier_char)
+        && !(skip_member_access && is_member_access(text, start))
+        && !(skip_property_keys && is_property_key(text, start, end))
+    }),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::MagicString;
+
+  fn generated() -> Option<FindOptions> {
+    Some(FindOptions {
+      generated: Some(true),
+    })
+  }
+
+  fn ranges(found: &[FindMatch]) -> Vec<(Option<u32>, Option<u32>)> {
+    found.iter().map(|m| (m.start, m.end)).collect()
+  }
+
+  #[test]
+  fn finds_the_first_match_with_its_captures() {
+    let s = MagicString::new("a1b22c", None);
+    let found = s.find(Pattern::Regex(r"(\d)(x)?"), None).unwrap().unwrap();
+    assert_eq!((found.start, found.end), (Some(1), Some(2)));
+    assert_eq!(
+      found.captures,
+      vec![
+        Some(MatchRange {
+          start: Some(1),
+          end: Some(2),
+          text: "1".to_string(),
+        }),
+        None,
+      ]
+    );
+    assert_eq!(s.find(Pattern::String("."), None).unwrap(), None);
+    assert!(s.find(Pattern::Regex("("), None).is_err());
+  }
+
+  #[test]
+  fn finds_empty_matches_at_every_position() {
+    let s = MagicString::new("ab", None);
+    let found = s.find_all(Pattern::Regex("x*"), None).unwrap();
+    assert_eq!(
+      ranges(&found),
+      vec![(Some(0), Some(0)), (Some(1), Some(1)), (Some(2), Some(2))]
+    );
+  }
+
+  #[test]
+  fn searches_the_original_regardless_of_edits() {
+    let mut s = MagicString::new("é = b; é", None);
+    s.overwrite(0, 2, "foo", None).unwrap();
+    let found = s.find_all(Pattern::String("é"), None).unwrap();
+    assert_eq!(
+      ranges(&found),
+      vec![(Some(0), Some(2)), (Some(8), Some(10))]
+    );
+    assert!(s.find_all(Pattern::String("foo"), None).unwrap().is_empty());
+  }
+
+  #[test]
+  fn maps_generated_matches_back_where_possible() {
+    let mut s = MagicString::new("let a = b;", None);
+    s.overwrite(4, 5, "foo", None).unwrap();
+    s.append_left(10, " // foo").unwrap();
+
+    let inserted = s.find_all(Pattern::String("foo"), generated()).unwrap();
+    assert_eq!(ranges(&inserted), vec![(None, None), (None, None)]);
+    let spanning = s.find(Pattern::String("foo = b"), generated()).unwrap();
+    assert_eq!(spanning.map(|m| (m.start, m.end)), Some((None, Some(9))));
+    let kept = s.find(Pattern::String("= b"), generated()).unwrap();
+    assert_eq!(kept.map(|m| (m.start, m.end)), Some((Some(6), Some(9))));
+  }
+
+  #[test]
+  fn finds_the_leftmost_longest_of_many_patterns() {
+    let found = find_many(
+      "process.env.NODE_ENV",
+      &["process.env", "process.env.NODE_ENV", "env"],
+      None,
+    )
+    .unwrap();
+    assert_eq!(found, vec![(0, 20, 1)]);
+    // empty patterns never match, the others keep their index
+    assert_eq!(
+      find_many("aaa", &["", "a", "aa"], None).unwrap(),
+      vec![(0, 2, 2), (2, 3, 1)]
+    );
+    // the first of duplicate patterns wins
+    assert_eq!(
+      find_many("ab", &["ab", "ab"], None).unwrap(),
+      vec![(0, 2, 0)]
+    );
+  }
+
+  #[test]
+  fn finds_whole_words_only() {
+    assert_eq!(
+      find_words("DEBUG DEBUGGER éDEBUG DEBUG_x", &["DEBUG"]).unwrap(),
+      vec![(0, 5, 0)]
+    );
+    // a shorter pattern matches where the longest one is not a whole word
+    assert_eq!(
+      find_words("a.bc a.b", &["a.bc", "a.b"]).unwrap(),
+      vec![(0, 4, 0), (5, 8, 1)]
+    );
+    assert!(find_words("a.bcd", &["a.bc", "a.b"]).unwrap().is_empty());
+  }
+
+  #[test]
+  fn replaces_many_patterns_in_one_pass() {
+    let mut s = MagicString::new("if (__DEV__ && __TEST__) {}", None);
+    s.replace_many(&[("__DEV__", "false"), ("__TEST__", "true")], None)
+      .unwrap();
+    assert_eq!(s.to_string(), "if (false && true) {}");
+
+    let mut s = MagicString::new("xDEBUG DEBUG", None);
+    s.replace_many(
+      &[("DEBUG", "0")],
+      Some(ReplaceManyOptions {
+        word_boundary: Some(true),
+      }),
+    )
+    .unwrap();
+    assert_eq!(s.to_string(), "xDEBUG 0");
+
+    let mut s = MagicString::new("ab", None);
+    s.replace_many(&[], None).unwrap();
+    assert_eq!(s.edit_count(), 0);
+  }
+
+  #[test]
+  fn fails_to_replace_many_across_an_edit() {
+    let mut s = MagicString::new("ab cd", None);
+    s.overwrite(1, 4, "X", None).unwrap();
+    assert!(s.replace_many(&[("ab", "Y")], None).is_err());
+    assert_eq!(s.to_string(), "aXd");
+  }
+}
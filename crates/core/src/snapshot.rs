@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use fms_sourcemap::{bit_set::BitSet, DecodedMap, SourceMap};
+
+use crate::{
+  chunk::ChunkSnapshot, result::Result, GenerateMapOptions, MagicString, MagicStringOptions,
+};
+
+// everything needed to render a `MagicString`, without the `Rc`s, so it can be sent to
+// another thread and turned back into a `MagicString` there, the source is shared
+#[derive(Clone)]
+pub struct MagicStringSnapshot {
+  pub(crate) original: Arc<str>,
+  pub(crate) intro: String,
+  pub(crate) outro: String,
+  pub(crate) chunks: Vec<ChunkSnapshot>,
+  pub(crate) stored_names: Vec<String>,
+  pub(crate) sourcemap_locations: BitSet,
+  pub(crate) indent_str: Option<String>,
+  pub(crate) edit_count: u64,
+  pub(crate) options: MagicStringOptions,
+}
+
+impl MagicStringSnapshot {
+  pub fn generate_map(self, options: Option<GenerateMapOptions>) -> Result<SourceMap> {
+    MagicString::from_snapshot(self).generate_map(options)
+  }
+
+  pub fn generate_decoded_map(self, options: Option<GenerateMapOptions>) -> Result<DecodedMap> {
+    MagicString::from_snapshot(self).generate_decoded_map(options)
+  }
+
+  pub fn generate(self, options: Option<GenerateMapOptions>) -> Result<(String, SourceMap)> {
+    MagicString::from_snapshot(self).generate(options)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn renders_the_same_map_on_another_thread() {
+    let mut s = MagicString::new("function foo() {}\nfoo()", None);
+    s.overwrite(9, 12, "bar", None).unwrap();
+    s.prepend("// x\n").unwrap();
+    let options = || {
+      Some(GenerateMapOptions {
+        hires: Some(true),
+        ..Default::default()
+      })
+    };
+    let expected = s.generate_map(options()).unwrap().mappings;
+
+    let snapshot = s.snapshot();
+    let mappings = std::thread::spawn(move || snapshot.generate_map(options()).unwrap().mappings)
+      .join()
+      .unwrap();
+    assert_eq!(mappings, expected);
+  }
+
+  #[test]
+  fn shares_the_source() {
+    let mut s = MagicString::new("abc", None);
+    s.overwrite(1, 2, "B", None).unwrap();
+    let snapshot = s.snapshot();
+    assert!(Arc::ptr_eq(&s.original, &snapshot.original));
+
+    let restored = MagicString::from_snapshot(snapshot);
+    assert!(Arc::ptr_eq(&s.original, &restored.original));
+    assert_eq!(restored.to_string(), "aBc");
+  }
+}
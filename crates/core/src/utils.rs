@@ -1,7 +1,8 @@
 use crate::error::{Error, FmsErrType};
 use crate::result::Result;
+use crate::{IndentStyle, LineEnding};
 
-pub fn slice_string(s: String, start: usize, end: usize) -> String {
+pub fn slice_string(s: &str, start: usize, end: usize) -> String {
   s[start..end].to_owned()
 }
 
@@ -24,25 +25,159 @@ pub fn normalize_range(str: &str, start: i32, end: i32) -> Result<(u32, u32)> {
       "end is out of bounds",
     ));
   }
+  // slicing there would panic
+  for index in [_start, _end] {
+    if index < len && !str.is_char_boundary(index as usize) {
+      return Err(Error::from_reason(
+        FmsErrType::Range,
+        format!("Index {} is inside a character", index).as_str(),
+      ));
+    }
+  }
   Ok((_start as u32, _end as u32))
 }
 
-use regex::{Captures, Regex};
+// the byte offset of a JS string index into `text`, rounded up inside a surrogate pair and
+// past the end of `text` when `index` is
+pub fn utf16_to_byte_index(text: &str, index: usize) -> usize {
+  let mut units = 0;
+  for (i, c) in text.char_indices() {
+    if units >= index {
+      return i;
+    }
+    units += c.len_utf16();
+  }
+  text.len() + index.saturating_sub(units)
+}
+
+pub fn byte_to_utf16_index(text: &str, index: usize) -> usize {
+  text[..index].encode_utf16().count()
+}
+
+// converts between byte offsets into a text and its JS string indices, only the non-ASCII
+// characters are kept so an ASCII text needs no table at all
+#[derive(Clone, Default)]
+pub struct Utf16Index {
+  // ascending by both offsets
+  wide_chars: Vec<WideChar>,
+  len: u32,
+}
+
+#[derive(Clone)]
+struct WideChar {
+  byte: u32,
+  utf16: u32,
+  byte_len: u8,
+  utf16_len: u8,
+}
+
+impl Utf16Index {
+  pub fn new(text: &str) -> Self {
+    if text.is_ascii() {
+      return Self {
+        wide_chars: vec![],
+        len: text.len() as u32,
+      };
+    }
+    let mut wide_chars = vec![];
+    let mut utf16 = 0;
+    for (byte, c) in text.char_indices() {
+      if !c.is_ascii() {
+        wide_chars.push(WideChar {
+          byte: byte as u32,
+          utf16,
+          byte_len: c.len_utf8() as u8,
+          utf16_len: c.len_utf16() as u8,
+        });
+      }
+      utf16 += c.len_utf16() as u32;
+    }
+    Self {
+      wide_chars,
+      len: utf16,
+    }
+  }
+
+  // length of the text in UTF-16 code units
+  pub fn len(&self) -> u32 {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  // the byte offset of a JS string index, indices past the end stay as far past the end,
+  // `None` between the halves of a surrogate pair or when the offset would overflow
+  pub fn byte_index(&self, index: u32) -> Option<u32> {
+    let i = self.wide_chars.partition_point(|c| c.utf16 <= index);
+    let Some(c) = i.checked_sub(1).map(|i| &self.wide_chars[i]) else {
+      return Some(index);
+    };
+    let utf16_end = c.utf16 + c.utf16_len as u32;
+    if index == c.utf16 {
+      Some(c.byte)
+    } else if index < utf16_end {
+      None
+    } else {
+      (c.byte + c.byte_len as u32).checked_add(index - utf16_end)
+    }
+  }
+
+  // the JS string index of a byte offset, rounded up inside a character
+  pub fn utf16_index(&self, index: u32) -> u32 {
+    let i = self.wide_chars.partition_point(|c| c.byte < index);
+    let Some(c) = i.checked_sub(1).map(|i| &self.wide_chars[i]) else {
+      return index;
+    };
+    let byte_end = c.byte + c.byte_len as u32;
+    c.utf16 + c.utf16_len as u32 + index.saturating_sub(byte_end)
+  }
+}
+
+use fms_regexp::{Captures, CompiledRegex};
+
+pub fn match_all<'a>(re: &CompiledRegex, text: &'a str, global: bool) -> Result<Vec<Captures<'a>>> {
+  match_all_in(re, text, 0, text.len(), global)
+}
 
-pub fn match_all<'a>(re: &Regex, text: &'a str, global: bool) -> (Vec<Captures<'a>>, Vec<usize>) {
-  let mut matches = Vec::new();
-  let mut start = 0;
-  let mut offsets = Vec::new();
+// matches inside `start..end`, the whole `text` still counts for anchors, word boundaries and
+// lookaround, matches crossing `end` are skipped, positions are relative to `text`
+pub fn match_all_in<'a>(
+  re: &CompiledRegex,
+  text: &'a str,
+  start: usize,
+  end: usize,
+  global: bool,
+) -> Result<Vec<Captures<'a>>> {
+  let next_char = |i: usize| i + text[i..].chars().next().map_or(1, char::len_utf8);
+  let mut matches = vec![];
+  let mut pos = start;
 
-  while let Some(captures) = re.captures(&text[start..]) {
-    offsets.push(start);
-    start += captures.get(0).unwrap().end();
+  while pos <= end {
+    let Some(captures) = re.captures_at(text, pos)? else {
+      break;
+    };
+    let m = captures.get(0).unwrap();
+    if m.start() > end {
+      break;
+    }
+    if m.end() > end {
+      // a later match may still end in range
+      pos = next_char(m.start());
+      continue;
+    }
+    pos = if m.is_empty() {
+      next_char(m.end())
+    } else {
+      m.end()
+    };
     matches.push(captures);
     if !global {
-      return (matches, offsets);
+      break;
     }
   }
-  (matches, offsets)
+  Ok(matches)
 }
 
 pub fn guess_indent(code: &str) -> Result<String> {
@@ -70,3 +205,159 @@ pub fn guess_indent(code: &str) -> Result<String> {
 
   Ok(" ".repeat(min_spaces))
 }
+
+// length of the indentation prefix shared by every non-blank line, tabs never match spaces
+pub fn min_indent(code: &str) -> usize {
+  let mut common: Option<&str> = None;
+  for line in code
+    .split(['\n', '\r'])
+    .filter(|line| !line.trim().is_empty())
+  {
+    let indent = &line[..line.len() - line.trim_start_matches([' ', '\t']).len()];
+    common = Some(match common {
+      Some(common) => {
+        let shared = common
+          .bytes()
+          .zip(indent.bytes())
+          .take_while(|(a, b)| a == b)
+          .count();
+        &common[..shared]
+      }
+      None => indent,
+    });
+  }
+  common.map_or(0, str::len)
+}
+
+pub fn is_indent_char(c: char) -> bool {
+  c == ' ' || c == '\t'
+}
+
+// `pending` is the amount of indentation still to be stripped from the current line, returns
+// whether `c` is stripped, a lone `\r` starts a new line too
+pub fn dedent_char(c: char, amount: usize, pending: &mut usize) -> bool {
+  if *pending > 0 && is_indent_char(c) {
+    *pending -= 1;
+    return true;
+  }
+  *pending = if c == '\n' || c == '\r' { amount } else { 0 };
+  false
+}
+
+pub fn dedent_str(str: &str, amount: usize, pending: &mut usize) -> String {
+  str
+    .chars()
+    .filter(|&c| !dedent_char(c, amount, pending))
+    .collect()
+}
+
+// byte offsets of the line break characters in `str` that change, with their replacement,
+// `before` and `after` are the characters rendered around `str` so a split `\r\n` is one break
+pub fn line_ending_edits(
+  str: &str,
+  line_ending: LineEnding,
+  before: Option<char>,
+  after: Option<char>,
+) -> Vec<(usize, &'static str)> {
+  let mut edits = vec![];
+  let mut prev = before;
+  let mut chars = str.char_indices().peekable();
+  while let Some((i, c)) = chars.next() {
+    let next = chars.peek().map(|&(_, c)| c).or(after);
+    let edit = match (c, line_ending) {
+      // `\r\n` -> `\n`, keep the `\n` so it maps to itself
+      ('\r', LineEnding::Lf) if next == Some('\n') => Some(""),
+      ('\r', _) if next != Some('\n') => Some(line_ending.as_str()),
+      ('\n', LineEnding::CrLf) if prev != Some('\r') => Some(line_ending.as_str()),
+      _ => None,
+    };
+    if let Some(edit) = edit {
+      edits.push((i, edit));
+    }
+    prev = Some(c);
+  }
+  edits
+}
+
+pub fn apply_line_ending_edits(str: &str, edits: &[(usize, &str)]) -> String {
+  let mut normalized = String::with_capacity(str.len());
+  let mut last = 0;
+  for &(i, edit) in edits {
+    normalized.push_str(&str[last..i]);
+    normalized.push_str(edit);
+    last = i + 1;
+  }
+  normalized.push_str(&str[last..]);
+  normalized
+}
+
+// byte ranges of the `from` indentation units in leading whitespace,
+// `at_line_start` carries whether the next character is still part of a line's indentation
+pub fn indent_units(str: &str, from: IndentStyle, at_line_start: &mut bool) -> Vec<(usize, usize)> {
+  let mut units = vec![];
+  let mut run_start = 0;
+  let mut run_len = 0;
+  for (i, c) in str.char_indices() {
+    if c == '\n' || c == '\r' {
+      *at_line_start = true;
+      run_len = 0;
+      continue;
+    }
+    if !*at_line_start {
+      continue;
+    }
+    match (c, from) {
+      ('\t', IndentStyle::Tab) => units.push((i, i + 1)),
+      (' ', IndentStyle::Spaces(width)) => {
+        if run_len == 0 {
+          run_start = i;
+        }
+        run_len += 1;
+        if run_len == width {
+          units.push((run_start, i + 1));
+          run_len = 0;
+        }
+      }
+      (' ' | '\t', _) => run_len = 0,
+      _ => *at_line_start = false,
+    }
+  }
+  units
+}
+
+pub fn reindent_str(str: &str, from: IndentStyle, to: &str, at_line_start: &mut bool) -> String {
+  let mut reindented = String::with_capacity(str.len());
+  let mut last = 0;
+  for (start, end) in indent_units(str, from, at_line_start) {
+    reindented.push_str(&str[last..start]);
+    reindented.push_str(to);
+    last = end;
+  }
+  reindented.push_str(&str[last..]);
+  reindented
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn converts_between_byte_and_utf16_indices() {
+    // `é` is 2 bytes and 1 unit, `😀` 4 bytes and 2 units
+    let text = "aé😀b";
+    let index = Utf16Index::new(text);
+    assert_eq!(index.len(), 5);
+    let pairs = [(0, 0), (1, 1), (3, 2), (7, 4), (8, 5), (10, 7)];
+    for (byte, utf16) in pairs {
+      assert_eq!(index.byte_index(utf16), Some(byte));
+      assert_eq!(index.utf16_index(byte), utf16);
+    }
+    assert_eq!(index.byte_index(3), None);
+    assert_eq!(index.utf16_index(5), 4);
+    assert_eq!(index.byte_index(u32::MAX), None);
+
+    let ascii = Utf16Index::new("abc");
+    assert_eq!(ascii.byte_index(2), Some(2));
+    assert_eq!(ascii.utf16_index(5), 5);
+  }
+}
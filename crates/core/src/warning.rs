@@ -0,0 +1,128 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WarningCode {
+  // a magic-string API kept for compatibility
+  DeprecatedApi,
+  // an option that was passed but had no effect
+  IgnoredOption,
+  // an edit the sourcemap can't map back to the original
+  LossyMapping,
+}
+
+impl WarningCode {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      WarningCode::DeprecatedApi => "FMS_DEPRECATED_API",
+      WarningCode::IgnoredOption => "FMS_IGNORED_OPTION",
+      WarningCode::LossyMapping => "FMS_LOSSY_MAPPING",
+    }
+  }
+}
+
+// recorded on the `MagicString` that raised it, see `MagicString::take_warnings`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Warning {
+  pub code: WarningCode,
+  // the magic-string API that raised it, in its JS spelling
+  pub api: &'static str,
+  pub message: String,
+}
+
+impl Warning {
+  pub fn new(code: WarningCode, api: &'static str, message: &str) -> Self {
+    Self {
+      code,
+      api,
+      message: message.to_string(),
+    }
+  }
+}
+
+impl fmt::Display for Warning {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "[{}] {}", self.code.as_str(), self.message)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{IndentOptions, IndentStyle, MagicString, ReindentOptions};
+
+  #[test]
+  fn records_warnings_until_taken() {
+    let mut s = MagicString::new("abc", None);
+    s.insert_left(1, "x").unwrap();
+    s.indent(
+      Some("  ".to_string()),
+      Some(IndentOptions {
+        exclude: Some(vec![vec![0]]),
+        indent_start: None,
+      }),
+    )
+    .unwrap();
+
+    let codes: Vec<WarningCode> = s.take_warnings().iter().map(|w| w.code).collect();
+    assert_eq!(
+      codes,
+      vec![WarningCode::DeprecatedApi, WarningCode::IgnoredOption]
+    );
+    assert!(s.take_warnings().is_empty());
+    assert_eq!(s.to_string(), "  axbc");
+  }
+
+  #[test]
+  fn warns_about_malformed_exclusion_ranges() {
+    let mut s = MagicString::new("  a\n  b", None);
+    s.dedent(
+      None,
+      Some(IndentOptions {
+        exclude: Some(vec![vec![0, 1, 2]]),
+        indent_start: None,
+      }),
+    )
+    .unwrap();
+    s.reindent(
+      None,
+      IndentStyle::Tab,
+      Some(ReindentOptions {
+        exclude: Some(vec![vec![1]]),
+        ..Default::default()
+      }),
+    )
+    .unwrap();
+
+    let codes: Vec<WarningCode> = s.take_warnings().iter().map(|w| w.code).collect();
+    assert_eq!(
+      codes,
+      vec![WarningCode::IgnoredOption, WarningCode::IgnoredOption]
+    );
+    assert_eq!(s.to_string(), "a\nb");
+  }
+
+  #[test]
+  fn warns_about_locations_inside_edits() {
+    let mut s = MagicString::new("abcdef", None);
+    s.add_sourcemap_location(1);
+    s.add_sourcemap_location(4);
+    s.overwrite(4, 6, "X", None).unwrap();
+    s.generate_map(None).unwrap();
+    assert!(s.take_warnings().is_empty());
+
+    s.overwrite(0, 3, "Y", None).unwrap();
+    s.generate(None).unwrap();
+    let warnings = s.take_warnings();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].code, WarningCode::LossyMapping);
+    assert_eq!(warnings[0].api, "generate");
+  }
+
+  #[test]
+  fn maps_without_warnings() {
+    let mut s = MagicString::new("", None);
+    s.append("x").unwrap();
+    s.generate_map(None).unwrap();
+    assert!(s.take_warnings().is_empty());
+  }
+}
@@ -2,14 +2,19 @@
 /* eslint-disable */
 export declare class MagicString {
   indentExclusionRanges?: Array<number> | Array<Array<number>>
-  constructor(str: string, options?: JsMagicStringOptions | undefined | null)
+  constructor(str: string | Buffer | Uint8Array, options?: JsMagicStringOptions | undefined | null)
   addSourcemapLocation(index: number): this
   append(input: string): this
   appendLeft(index: number, input: string): this
   clone(): MagicString
-  generateMap(options?: JsGenerateMapOptions | undefined | null): JsSourceMap
+  generateMap(options?: JsGenerateMapOptions | undefined | null): SourceMap
+  generateMapAsync(options?: JsGenerateMapOptions | undefined | null): Promise<SourceMap>
   generateDecodedMap(options?: JsGenerateMapOptions | undefined | null): JsDecodedMap
+  generate(options?: JsGenerateMapOptions | undefined | null): GenerateResult
+  generateAsync(options?: JsGenerateMapOptions | undefined | null): Promise<GenerateResult>
   indent(indentStr?: string | undefined | null, options?: JsIndentOptions | undefined | null): this
+  dedent(amount?: number | undefined | null, options?: JsIndentOptions | undefined | null): this
+  reindent(from: 'tab' | number | null | undefined, to: 'tab' | number, options?: JsReindentOptions | undefined | null): this
   insert(): void
   insertLeft(index: number, input: string): this
   appendRight(index: number, input: string): this
@@ -25,22 +30,77 @@ export declare class MagicString {
   remove(start: number, end: number): this
   overwrite(start: number, end: number, content: string, options?: JsOverwriteOptions | undefined | null): this
   update(start: number, end: number, content: string, options?: JsOverwriteOptions | undefined | null): this
+  overwriteAt(start: JsPosition, end: JsPosition, content: string, options?: JsOverwriteOptions | undefined | null): this
+  removeAt(start: JsPosition, end: JsPosition): this
+  appendLeftAt(position: JsPosition, input: string): this
+  prependRightAt(position: JsPosition, input: string): this
+  sliceAt(start: JsPosition, end: JsPosition): string
+  indexOf(line: number, column: number): number
+  positionOf(index: number): JsPosition
+  chunks(): Array<JsChunk>
+  generatedPositionFor(originalIndex: number): JsMappedPosition
+  originalPositionFor(generatedIndex: number): JsMappedPosition
+  normalizeLineEndings(lineEnding: 'lf' | 'crlf'): this
   isEmpty(): boolean
   toString(): string
+  toBuffer(): Buffer
+  generateEncodedMappings(options?: JsGenerateMapOptions | undefined | null): Uint8Array
+  takeWarnings(): Array<JsWarning>
+  get original(): string
+  get offset(): number
+  set offset(offset: number)
+  length(): number
+  lastChar(): string
+  lastLine(): string
+  getIndentString(): string
   hasChanged(): boolean
   snip(start: number, end: number): MagicString
   slice(start?: number | undefined | null, end?: number | undefined | null): string
   reset(start: number, end: number): this
-  replace(pattern: RegExp | string, replacement: string | (any)): this
-  replaceAll(pattern: RegExp | string, replacement: string | (any)): this
+  find(pattern: RegExp | string, options?: JsFindOptions | undefined | null): JsFindMatch | null
+  findAll(pattern: RegExp | string, options?: JsFindOptions | undefined | null): Array<JsFindMatch>
+  matchAll(regexp: RegExp): Array<RegExpMatchArray>
+  replaceInRange(start: number, end: number, pattern: RegExp | string, replacement: string): this
+  replaceAllInRange(start: number, end: number, pattern: RegExp | string, replacement: string): this
+  replaceMany(replacements: Record<string, string>, options?: JsReplaceManyOptions | undefined | null): this
+  replaceIdentifiers(replacements: Record<string, string>, options?: JsIdentifierOptions | undefined | null): this
+  replace(pattern: RegExp | string, replacement: string | (any), options?: JsReplaceOptions | undefined | null): this
+  replaceAll(pattern: RegExp | string, replacement: string | (any), options?: JsReplaceOptions | undefined | null): this
 }
 export type JsMagicString = MagicString
 
+export declare class SourceMap {
+  version: number
+  file?: string
+  sourceRoot?: string
+  sources: Array<string>
+  sourcesContent?: Array<string>
+  names: Array<string>
+  mappings: string
+  x_google_ignoreList?: Array<number>
+  toString(): string
+  toUrl(): string
+  toJSON(): JsSourceMapJson
+}
+export type JsSourceMap = SourceMap
+
 export interface FmsRegex {
   global?: boolean
   rule: string
 }
 
+export interface JsChunk {
+  start: number
+  end: number
+  original: string
+  intro: string
+  content: string
+  outro: string
+  edited: boolean
+  storeName: boolean
+  generatedOffset: number
+}
+
 export interface JsDecodedMap {
   version: number
   file?: string
@@ -60,15 +120,71 @@ export interface JsGenerateMapOptions {
   hires?: boolean
 }
 
+export interface JsFindOptions {
+  generated?: boolean
+}
+
+export interface JsReplaceOptions {
+  target?: 'original' | 'generated'
+}
+
+export interface JsReplaceManyOptions {
+  wordBoundary?: boolean
+}
+
+export interface JsIdentifierOptions {
+  identifierChars?: string
+  skipMemberAccess?: boolean
+  skipPropertyKeys?: boolean
+}
+
+export interface JsMatchRange {
+  start?: number
+  end?: number
+  text: string
+}
+
+export interface JsFindMatch {
+  start?: number
+  end?: number
+  text: string
+  captures: Array<JsMatchRange | undefined | null>
+}
+
+export interface GenerateResult {
+  code: string
+  map: SourceMap
+}
+
 export interface JsIndentOptions {
   exclude?: Array<Array<number>>
   indentStart?: boolean
 }
 
+export interface JsReindentOptions {
+  start?: number
+  end?: number
+  exclude?: Array<Array<number>>
+}
+
 export interface JsMagicStringOptions {
   filename?: string
   indentExclusionRanges?: Array<number> | Array<Array<number>>
   ignoreList?: boolean
+  oneBasedLines?: boolean
+  offset?: number
+  /**
+   * Keep warnings for `takeWarnings()` instead of passing them to `process.emitWarning`,
+   * which gets each code only once per process and API, from whichever instance raises it first
+   */
+  collectWarnings?: boolean
+}
+
+export interface JsMappedPosition {
+  kind: 'exact' | 'insideEdit' | 'removed'
+  index?: number
+  start?: number
+  end?: number
 }
 
 export interface JsOverwriteOptions {
@@ -77,7 +193,12 @@ export interface JsOverwriteOptions {
   overwrite?: boolean
 }
 
-export interface JsSourceMap {
+export interface JsPosition {
+  line: number
+  column: number
+}
+
+export interface JsSourceMapJson {
   version: number
   file?: string
   sourceRoot?: string
@@ -85,5 +206,10 @@ export interface JsSourceMap {
   sourcesContent?: Array<string>
   names: Array<string>
   mappings: string
-  xGoogleIgnoreList?: Array<number>
+  x_google_ignoreList?: Array<number>
+}
+
+export interface JsWarning {
+  code: 'FMS_DEPRECATED_API' | 'FMS_IGNORED_OPTION' | 'FMS_LOSSY_MAPPING'
+  message: string
 }
@@ -366,3 +366,5 @@ if (!nativeBinding) {
 
 module.exports.MagicString = nativeBinding.MagicString
 module.exports.JsMagicString = nativeBinding.JsMagicString
+module.exports.SourceMap = nativeBinding.SourceMap
+module.exports.JsSourceMap = nativeBinding.JsSourceMap
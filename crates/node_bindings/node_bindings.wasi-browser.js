@@ -70,3 +70,5 @@ function __napi_rs_initialize_modules(__napiInstance) {
 }
 export const MagicString = __napiModule.exports.MagicString
 export const JsMagicString = __napiModule.exports.JsMagicString
+export const SourceMap = __napiModule.exports.SourceMap
+export const JsSourceMap = __napiModule.exports.JsSourceMap
@@ -94,3 +94,5 @@ function __napi_rs_initialize_modules(__napiInstance) {
 }
 module.exports.MagicString = __napiModule.exports.MagicString
 module.exports.JsMagicString = __napiModule.exports.JsMagicString
+module.exports.SourceMap = __napiModule.exports.SourceMap
+module.exports.JsSourceMap = __napiModule.exports.JsSourceMap
@@ -37,6 +37,9 @@ pub fn to_napi_error(err: Error) -> napi::Error {
     FmsErrType::Slice => {
       reason.push_str("Slice error");
     }
+    FmsErrType::Regex => {
+      reason.push_str("Regex error");
+    }
   }
   reason.push_str(": ");
   reason.push_str(err.err_msg.unwrap_or_default().as_str());
@@ -4,49 +4,116 @@ extern crate napi;
 #[macro_use]
 extern crate napi_derive;
 
+use std::{
+  cell::{OnceCell, RefCell},
+  collections::HashMap,
+  rc::Rc,
+  str,
+};
+
 use error::to_napi_error;
-use fms_regexp::FmsRegex;
+use fms_regexp::{FmsRegex, JsRegExp};
 use napi::{
-  bindgen_prelude::{Either, Function},
-  Result,
+  bindgen_prelude::{AsyncTask, Buffer, Either, Function, Uint8Array},
+  Env, JsObject, Result,
 };
 
 extern crate fast_magic_string;
 
 use fast_magic_string::{
   error::{Error, FmsErrType},
-  MagicString,
+  search::{Pattern, ReplaceTarget},
+  utils::Utf16Index,
+  IndentExclusionRanges, IndentOptions, LineEnding, MagicString, MagicStringOptions,
+  ReindentOptions,
 };
 mod types;
 
+use source_map::SourceMapObject;
+use tasks::{GenerateMapTask, GenerateTask};
 use types::{
-  JsDecodedMap, JsGenerateMapOptions, JsIndentOptions, JsMagicStringOptions, JsOverwriteOptions,
-  JsSourceMap,
+  to_chunk, to_find_match, to_indent_style, to_mapped_position, to_match_array, to_regex_state,
+  to_replace_target, to_rust_pattern, JsChunk, JsDecodedMap, JsFindMatch, JsFindOptions,
+  JsGenerateMapOptions, JsGenerateResult, JsIdentifierOptions, JsIndentOptions,
+  JsMagicStringOptions, JsMappedPosition, JsOverwriteOptions, JsPosition, JsReindentOptions,
+  JsReplaceManyOptions, JsReplaceOptions,
 };
+use warnings::{JsWarning, WarningSink};
 
 mod error;
+mod source_map;
+mod tasks;
+mod warnings;
 
+// indices are JS string indices like in magic-string, core counts bytes, so they are
+// converted here on the way in and out
 #[napi(js_name = "MagicString")]
 struct JsMagicString {
   inner: MagicString,
   pub indent_exclusion_ranges: Option<Either<Vec<u32>, Vec<Vec<u32>>>>,
+  // emits the warnings or keeps them for `takeWarnings`
+  warnings: WarningSink,
+  // `inner` keeps the offset in bytes
+  offset: u32,
+  original_index: OnceCell<Utf16Index>,
+  // of the output after `edit_count` edits
+  generated_index: RefCell<Option<(u64, Rc<Utf16Index>)>>,
 }
 
 #[allow(dead_code)]
 #[napi]
 impl JsMagicString {
   #[napi(constructor)]
-  pub fn new(str: String, options: Option<JsMagicStringOptions>) -> JsMagicString {
-    JsMagicString {
-      indent_exclusion_ranges: options
+  pub fn new(
+    #[napi(ts_arg_type = "string | Buffer | Uint8Array")] str: Either<String, Uint8Array>,
+    options: Option<JsMagicStringOptions>,
+  ) -> Result<JsMagicString> {
+    let indent_exclusion_ranges = options
+      .as_ref()
+      .and_then(|o| o.indent_exclusion_ranges.clone());
+    let warnings = WarningSink::new(
+      options
         .as_ref()
-        .and_then(|o| o.indent_exclusion_ranges.clone()),
-      inner: MagicString::new(str.as_str(), options.map(|x| x.into())),
+        .and_then(|o| o.collect_warnings)
+        .unwrap_or_default(),
+    );
+    let offset = options.as_ref().and_then(|o| o.offset).unwrap_or_default();
+    let text = match &str {
+      Either::A(str) => str.as_str(),
+      // read in place, without converting to a JS string first
+      Either::B(bytes) => str::from_utf8(bytes).map_err(|err| to_napi_error(err.into()))?,
+    };
+
+    let original_index = OnceCell::new();
+    let mut options: Option<MagicStringOptions> = options.map(|x| x.into());
+    if let Some(options) = &mut options {
+      let index = original_index.get_or_init(|| Utf16Index::new(text));
+      if let Some(offset) = options.offset {
+        options.offset = Some(to_byte_index(index, offset)?);
+      }
+      options.indent_exclusion_ranges = match options.indent_exclusion_ranges.take() {
+        Some(IndentExclusionRanges::Single(range)) => Some(IndentExclusionRanges::Single(
+          to_byte_indices(index, range)?,
+        )),
+        Some(IndentExclusionRanges::Nested(ranges)) => Some(IndentExclusionRanges::Nested(
+          to_byte_ranges(index, ranges)?,
+        )),
+        None => None,
+      };
     }
+    Ok(JsMagicString {
+      indent_exclusion_ranges,
+      inner: MagicString::new(text, options),
+      warnings,
+      offset,
+      original_index,
+      generated_index: RefCell::default(),
+    })
   }
 
   #[napi]
   pub fn add_sourcemap_location(&mut self, index: u32) -> Result<&Self> {
+    let index = to_byte_index(self.original_index(), index)?;
     self.inner.add_sourcemap_location(index);
     Ok(self)
   }
@@ -59,6 +126,7 @@ impl JsMagicString {
 
   #[napi]
   pub fn append_left(&mut self, index: u32, input: String) -> Result<&Self> {
+    let index = self.inner_index(index)?;
     self
       .inner
       .append_left(index, input.as_str())
@@ -68,27 +136,40 @@ impl JsMagicString {
 
   #[napi]
   pub fn clone(&self) -> JsMagicString {
-    let inner = self.inner._clone();
-    let indent_exclusion_ranges = self.indent_exclusion_ranges.clone();
-    JsMagicString {
-      indent_exclusion_ranges,
-      inner,
-    }
+    self.derive(self.inner._clone())
   }
 
-  #[napi]
-  pub fn generate_map(&mut self, options: Option<JsGenerateMapOptions>) -> Result<JsSourceMap> {
+  #[napi(ts_return_type = "SourceMap")]
+  pub fn generate_map(
+    &mut self,
+    env: Env,
+    options: Option<JsGenerateMapOptions>,
+  ) -> Result<SourceMapObject> {
     let map = self
       .inner
-      .generate_map(options.map(|x| x.into()))
+      .generate_decoded_map(options.map(|x| x.into()))
       .map_err(to_napi_error)?
       .into();
+    self.flush_warnings(&env)?;
     Ok(map)
   }
 
+  #[napi(ts_return_type = "Promise<SourceMap>")]
+  pub fn generate_map_async(
+    &self,
+    options: Option<JsGenerateMapOptions>,
+  ) -> AsyncTask<GenerateMapTask> {
+    AsyncTask::new(GenerateMapTask::new(
+      self.inner.snapshot(),
+      options.map(|x| x.into()),
+      self.warnings.clone(),
+    ))
+  }
+
   #[napi]
   pub fn generate_decoded_map(
     &mut self,
+    env: Env,
     options: Option<JsGenerateMapOptions>,
   ) -> Result<JsDecodedMap> {
     let decoded_map = self
@@ -96,19 +177,95 @@ impl JsMagicString {
       .generate_decoded_map(options.map(|x| x.into()))
       .map_err(to_napi_error)?
       .into();
+    self.flush_warnings(&env)?;
     Ok(decoded_map)
   }
 
+  #[napi]
+  pub fn generate(
+    &mut self,
+    env: Env,
+    options: Option<JsGenerateMapOptions>,
+  ) -> Result<JsGenerateResult> {
+    let result = self
+      .inner
+      .generate_decoded(options.map(|x| x.into()))
+      .map_err(to_napi_error)?
+      .into();
+    self.flush_warnings(&env)?;
+    Ok(result)
+  }
+
+  #[napi(ts_return_type = "Promise<GenerateResult>")]
+  pub fn generate_async(&self, options: Option<JsGenerateMapOptions>) -> AsyncTask<GenerateTask> {
+    AsyncTask::new(GenerateTask::new(
+      self.inner.snapshot(),
+      options.map(|x| x.into()),
+      self.warnings.clone(),
+    ))
+  }
+
   #[napi]
   pub fn indent(
     &mut self,
+    env: Env,
     indent_str: Option<String>,
     options: Option<JsIndentOptions>,
   ) -> Result<&Self> {
+    let options = self.to_indent_options(options)?;
     self
       .inner
-      .indent(indent_str, options.map(|x| x.into()))
+      .indent(indent_str, options)
       .map_err(to_napi_error)?;
+    self.flush_warnings(&env)?;
+    Ok(self)
+  }
+
+  #[napi]
+  pub fn dedent(
+    &mut self,
+    env: Env,
+    amount: Option<u32>,
+    options: Option<JsIndentOptions>,
+  ) -> Result<&Self> {
+    let options = self.to_indent_options(options)?;
+    self.inner.dedent(amount, options).map_err(to_napi_error)?;
+    self.flush_warnings(&env)?;
+    Ok(self)
+  }
+
+  #[napi]
+  pub fn reindent(
+    &mut self,
+    env: Env,
+    #[napi(ts_arg_type = "'tab' | number | null | undefined")] from: Option<Either<String, u32>>,
+    #[napi(ts_arg_type = "'tab' | number")] to: Either<String, u32>,
+    options: Option<JsReindentOptions>,
+  ) -> Result<&Self> {
+    let from = from
+      .map(to_indent_style)
+      .transpose()
+      .map_err(to_napi_error)?;
+    let to = to_indent_style(to).map_err(to_napi_error)?;
+    let mut options: Option<ReindentOptions> = options.map(|x| x.into());
+    if let Some(options) = &mut options {
+      let index = self.original_index();
+      options.start = options
+        .start
+        .map(|i| to_byte_offset(index, i))
+        .transpose()?;
+      options.end = options.end.map(|i| to_byte_offset(index, i)).transpose()?;
+      options.exclude = options
+        .exclude
+        .take()
+        .map(|ranges| to_byte_ranges(index, ranges))
+        .transpose()?;
+    }
+    self
+      .inner
+      .reindent(from, to, options)
+      .map_err(to_napi_error)?;
+    self.flush_warnings(&env)?;
     Ok(self)
   }
 
@@ -121,17 +278,19 @@ impl JsMagicString {
   }
 
   #[napi]
-  pub fn insert_left(&mut self, index: u32, input: String) -> Result<&Self> {
-    println!("magicString.insertLeft(...) is deprecated. Use magicString.appendLeft(...) instead");
+  pub fn insert_left(&mut self, env: Env, index: u32, input: String) -> Result<&Self> {
+    let index = self.inner_index(index)?;
     self
       .inner
-      .append_left(index, input.as_str())
+      .insert_left(index, input.as_str())
       .map_err(to_napi_error)?;
+    self.flush_warnings(&env)?;
     Ok(self)
   }
 
   #[napi]
   pub fn append_right(&mut self, index: u32, input: String) -> Result<&Self> {
+    let index = self.inner_index(index)?;
     self
       .inner
       .append_right(index, input.as_str())
@@ -147,6 +306,7 @@ impl JsMagicString {
 
   #[napi]
   pub fn prepend_left(&mut self, index: u32, input: String) -> Result<&Self> {
+    let index = self.inner_index(index)?;
     self
       .inner
       .prepend_left(index, input.as_str())
@@ -156,6 +316,7 @@ impl JsMagicString {
 
   #[napi]
   pub fn prepend_right(&mut self, index: u32, input: String) -> Result<&Self> {
+    let index = self.inner_index(index)?;
     self
       .inner
       .prepend_right(index, input.as_str())
@@ -164,49 +325,60 @@ impl JsMagicString {
   }
 
   #[napi]
-  pub fn insert_right(&mut self, index: u32, input: String) -> Result<&Self> {
-    println!(
-      "magicString.insertRight(...) is deprecated. Use magicString.prependRight(...) instead"
-    );
+  pub fn insert_right(&mut self, env: Env, index: u32, input: String) -> Result<&Self> {
+    let index = self.inner_index(index)?;
     self
       .inner
-      .prepend_right(index, input.as_str())
+      .insert_right(index, input.as_str())
       .map_err(to_napi_error)?;
+    self.flush_warnings(&env)?;
     Ok(self)
   }
 
   #[napi]
   pub fn trim(&mut self, char_type: Option<String>) -> Result<&Self> {
-    self.inner.trim(char_type.as_deref());
+    self
+      .inner
+      .trim(char_type.as_deref())
+      .map_err(to_napi_error)?;
     Ok(self)
   }
 
   #[napi]
   pub fn trim_lines(&mut self) -> Result<&Self> {
-    self.inner.trim_lines();
+    self.inner.trim_lines().map_err(to_napi_error)?;
     Ok(self)
   }
 
   #[napi]
   pub fn trim_start(&mut self, char_type: Option<String>) -> Result<&Self> {
-    self.inner.trim_start(char_type.as_deref());
+    self
+      .inner
+      .trim_start(char_type.as_deref())
+      .map_err(to_napi_error)?;
     Ok(self)
   }
 
   #[napi]
   pub fn trim_end(&mut self, char_type: Option<String>) -> Result<&Self> {
-    self.inner.trim_end(char_type.as_deref());
+    self
+      .inner
+      .trim_end(char_type.as_deref())
+      .map_err(to_napi_error)?;
     Ok(self)
   }
 
   #[napi(js_name = "move")]
   pub fn _move(&mut self, start: i32, end: i32, index: u32) -> Result<&Self> {
+    let (start, end) = self.inner_range(start, end)?;
+    let index = self.inner_index(index)?;
     self.inner._move(start, end, index).map_err(to_napi_error)?;
     Ok(self)
   }
 
   #[napi]
   pub fn remove(&mut self, start: i32, end: i32) -> Result<&Self> {
+    let (start, end) = self.inner_range(start, end)?;
     self.inner.remove(start, end).map_err(to_napi_error)?;
     Ok(self)
   }
@@ -219,6 +391,7 @@ impl JsMagicString {
     content: String,
     options: Option<JsOverwriteOptions>,
   ) -> Result<&Self> {
+    let (start, end) = self.inner_range(start, end)?;
     self
       .inner
       .overwrite(start, end, content.as_str(), options.map(|x| x.into()))
@@ -234,6 +407,7 @@ impl JsMagicString {
     content: String,
     options: Option<JsOverwriteOptions>,
   ) -> Result<&Self> {
+    let (start, end) = self.inner_range(start, end)?;
     self
       .inner
       .update(start, end, content.as_str(), options.map(|x| x.into()))
@@ -241,16 +415,206 @@ impl JsMagicString {
     Ok(self)
   }
 
+  #[napi]
+  pub fn overwrite_at(
+    &mut self,
+    start: JsPosition,
+    end: JsPosition,
+    content: String,
+    options: Option<JsOverwriteOptions>,
+  ) -> Result<&Self> {
+    self
+      .inner
+      .overwrite_at(
+        start.into(),
+        end.into(),
+        content.as_str(),
+        options.map(|x| x.into()),
+      )
+      .map_err(to_napi_error)?;
+    Ok(self)
+  }
+
+  #[napi]
+  pub fn remove_at(&mut self, start: JsPosition, end: JsPosition) -> Result<&Self> {
+    self
+      .inner
+      .remove_at(start.into(), end.into())
+      .map_err(to_napi_error)?;
+    Ok(self)
+  }
+
+  #[napi]
+  pub fn append_left_at(&mut self, position: JsPosition, input: String) -> Result<&Self> {
+    self
+      .inner
+      .append_left_at(position.into(), input.as_str())
+      .map_err(to_napi_error)?;
+    Ok(self)
+  }
+
+  #[napi]
+  pub fn prepend_right_at(&mut self, position: JsPosition, input: String) -> Result<&Self> {
+    self
+      .inner
+      .prepend_right_at(position.into(), input.as_str())
+      .map_err(to_napi_error)?;
+    Ok(self)
+  }
+
+  #[napi]
+  pub fn slice_at(&self, start: JsPosition, end: JsPosition) -> Result<String> {
+    self
+      .inner
+      .slice_at(start.into(), end.into())
+      .map_err(to_napi_error)
+  }
+
+  #[napi]
+  pub fn index_of(&self, line: u32, column: u32) -> Result<u32> {
+    let index = self.inner.index_of(line, column).map_err(to_napi_error)?;
+    Ok(self.original_index().utf16_index(index))
+  }
+
+  #[napi]
+  pub fn position_of(&self, index: u32) -> Result<JsPosition> {
+    let index = to_byte_index(self.original_index(), index)?;
+    let position = self.inner.position_of(index).map_err(to_napi_error)?;
+    Ok(position.into())
+  }
+
+  #[napi]
+  pub fn chunks(&self) -> Vec<JsChunk> {
+    let generated = self.generated_index();
+    self
+      .inner
+      .chunks()
+      .map(|chunk| to_chunk(chunk, self.original_index(), &generated))
+      .collect()
+  }
+
+  #[napi]
+  pub fn generated_position_for(&self, original_index: u32) -> Result<JsMappedPosition> {
+    let original_index = to_byte_index(self.original_index(), original_index)?;
+    let position = self
+      .inner
+      .generated_position_for(original_index)
+      .map_err(to_napi_error)?;
+    Ok(to_mapped_position(position, &self.generated_index()))
+  }
+
+  #[napi]
+  pub fn original_position_for(&self, generated_index: u32) -> Result<JsMappedPosition> {
+    let generated_index = to_byte_index(&self.generated_index(), generated_index)?;
+    let position = self
+      .inner
+      .original_position_for(generated_index)
+      .map_err(to_napi_error)?;
+    Ok(to_mapped_position(position, self.original_index()))
+  }
+
+  #[napi]
+  pub fn normalize_line_endings(
+    &mut self,
+    #[napi(ts_arg_type = "'lf' | 'crlf'")] line_ending: String,
+  ) -> Result<&Self> {
+    let line_ending = match line_ending.as_str() {
+      "lf" => LineEnding::Lf,
+      "crlf" => LineEnding::CrLf,
+      _ => {
+        return Err(to_napi_error(Error::from_reason(
+          FmsErrType::Type,
+          "`lineEnding` argument must be 'lf' or 'crlf'",
+        )));
+      }
+    };
+    self
+      .inner
+      .normalize_line_endings(line_ending)
+      .map_err(to_napi_error)?;
+    Ok(self)
+  }
+
   #[napi]
   pub fn is_empty(&self) -> bool {
     self.inner.is_empty()
   }
 
+  #[allow(clippy::inherent_to_string)]
   #[napi]
-  pub fn to_string(&mut self) -> String {
+  pub fn to_string(&self) -> String {
     self.inner.to_string()
   }
 
+  // UTF-8, the Buffer takes ownership of the rendered bytes instead of copying them
+  #[napi]
+  pub fn to_buffer(&self) -> Buffer {
+    self.inner.to_bytes().into()
+  }
+
+  #[napi]
+  pub fn generate_encoded_mappings(
+    &self,
+    env: Env,
+    options: Option<JsGenerateMapOptions>,
+  ) -> Result<Uint8Array> {
+    let mappings = self
+      .inner
+      .generate_encoded_mappings(options.map(|x| x.into()))
+      .map_err(to_napi_error)?;
+    self.flush_warnings(&env)?;
+    Ok(mappings.into())
+  }
+
+  #[napi]
+  pub fn take_warnings(&self) -> Vec<JsWarning> {
+    let mut warnings = self.warnings.take();
+    warnings.extend(self.inner.take_warnings());
+    warnings.into_iter().map(JsWarning::from).collect()
+  }
+
+  #[napi(getter)]
+  pub fn original(&self) -> String {
+    self.inner.original.to_string()
+  }
+
+  #[napi(getter)]
+  pub fn offset(&self) -> u32 {
+    self.offset
+  }
+
+  #[napi(setter, js_name = "offset")]
+  pub fn set_offset(&mut self, offset: u32) -> Result<()> {
+    let byte_offset = to_byte_index(self.original_index(), offset)?;
+    self.inner.set_offset(byte_offset);
+    self.offset = offset;
+    Ok(())
+  }
+
+  #[napi]
+  pub fn length(&self) -> u32 {
+    self.inner.len() as u32
+  }
+
+  #[napi]
+  pub fn last_char(&self) -> String {
+    self.inner.last_char().map(String::from).unwrap_or_default()
+  }
+
+  #[napi]
+  pub fn last_line(&self) -> String {
+    self.inner.last_line()
+  }
+
+  #[napi]
+  pub fn get_indent_string(&mut self) -> Result<String> {
+    self
+      .inner
+      .get_indent_string()
+      .map(String::from)
+      .map_err(to_napi_error)
+  }
+
   #[napi]
   pub fn has_changed(&self) -> bool {
     self.inner.has_changed()
@@ -258,47 +622,209 @@ impl JsMagicString {
 
   #[napi]
   pub fn snip(&mut self, start: i32, end: i32) -> Result<JsMagicString> {
+    let (start, end) = self.inner_range(start, end)?;
     let inner = self.inner.snip(start, end).map_err(to_napi_error)?;
-    let indent_exclusion_ranges = self.indent_exclusion_ranges.clone();
-    Ok(JsMagicString {
-      indent_exclusion_ranges,
-      inner,
-    })
+    Ok(self.derive(inner))
   }
 
   #[napi]
   pub fn slice(&mut self, start: Option<i32>, end: Option<i32>) -> Result<String> {
     let _start = start.unwrap_or(0);
-    let _end = end.unwrap_or(self.inner.original.len().try_into().unwrap());
-    Ok(self.inner.slice(_start, _end).map_err(to_napi_error)?)
+    // like magic-string, the default end is relative to `offset` too
+    let _end =
+      end.unwrap_or((i64::from(self.original_index().len()) - i64::from(self.offset)) as i32);
+    let (_start, _end) = self.inner_range(_start, _end)?;
+    self.inner.slice(_start, _end).map_err(to_napi_error)
   }
 
   #[napi]
   pub fn reset(&mut self, start: i32, end: i32) -> Result<&Self> {
+    let (start, end) = self.inner_range(start, end)?;
     self.inner.reset(start, end).map_err(to_napi_error)?;
     Ok(self)
   }
 
   #[napi]
-  pub fn replace(
+  pub fn find(
+    &self,
+    #[napi(ts_arg_type = "RegExp | string")] pattern: Either<String, FmsRegex>,
+    options: Option<JsFindOptions>,
+  ) -> Result<Option<JsFindMatch>> {
+    let found = match &pattern {
+      Either::A(str) => self
+        .inner
+        .find(Pattern::String(str), options.map(|x| x.into())),
+      Either::B(reg) => self.inner.find(
+        Pattern::Regex(&to_rust_pattern(reg).map_err(to_napi_error)?),
+        options.map(|x| x.into()),
+      ),
+    }
+    .map_err(to_napi_error)?;
+    Ok(found.map(|m| to_find_match(m, self.original_index())))
+  }
+
+  #[napi]
+  pub fn find_all(
+    &self,
+    #[napi(ts_arg_type = "RegExp | string")] pattern: Either<String, FmsRegex>,
+    options: Option<JsFindOptions>,
+  ) -> Result<Vec<JsFindMatch>> {
+    let found = match &pattern {
+      Either::A(str) => self
+        .inner
+        .find_all(Pattern::String(str), options.map(|x| x.into())),
+      Either::B(reg) => self.inner.find_all(
+        Pattern::Regex(&to_rust_pattern(reg).map_err(to_napi_error)?),
+        options.map(|x| x.into()),
+      ),
+    }
+    .map_err(to_napi_error)?;
+    Ok(
+      found
+        .into_iter()
+        .map(|m| to_find_match(m, self.original_index()))
+        .collect(),
+    )
+  }
+
+  #[napi(ts_return_type = "Array<RegExpMatchArray>")]
+  pub fn match_all(&self, env: Env, regexp: FmsRegex) -> Result<Vec<JsObject>> {
+    // https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/String/matchAll#regexp
+    // > If regexp is a regex, then it must have the global (g) flag set, or a TypeError is thrown.
+    if !regexp.global() {
+      return Err(to_napi_error(Error::from_reason(
+        FmsErrType::Type,
+        "matchAll called with a non-global RegExp argument",
+      )));
+    }
+    let found = self
+      .inner
+      .match_all(
+        &to_rust_pattern(&regexp).map_err(to_napi_error)?,
+        regexp.last_index,
+        regexp.sticky(),
+      )
+      .map_err(to_napi_error)?;
+    let input = env.create_string(&self.inner.original)?;
+    let offsets = self.original_index();
+    let has_indices = regexp.flags.contains('d');
+    found
+      .matches
+      .into_iter()
+      .map(|m| to_match_array(&env, m, &found.names, input, offsets, has_indices))
+      .collect()
+  }
+
+  #[napi]
+  pub fn replace_in_range(
     &mut self,
+    start: i32,
+    end: i32,
     #[napi(ts_arg_type = "RegExp | string")] pattern: Either<String, FmsRegex>,
+    replacement: String,
+  ) -> Result<&Self> {
+    let index = self.original_index();
+    let (start, end) = (to_byte_offset(index, start)?, to_byte_offset(index, end)?);
+    match &pattern {
+      Either::A(str) => {
+        self
+          .inner
+          .replace_in_range(start, end, Pattern::String(str), &replacement, false)
+      }
+      Either::B(reg) => self.inner.replace_in_range(
+        start,
+        end,
+        Pattern::Regex(&to_rust_pattern(reg).map_err(to_napi_error)?),
+        &replacement,
+        reg.global(),
+      ),
+    }
+    .map_err(to_napi_error)?;
+    Ok(self)
+  }
+
+  #[napi]
+  pub fn replace_all_in_range(
+    &mut self,
+    start: i32,
+    end: i32,
+    #[napi(ts_arg_type = "RegExp | string")] pattern: Either<String, FmsRegex>,
+    replacement: String,
+  ) -> Result<&Self> {
+    let index = self.original_index();
+    let (start, end) = (to_byte_offset(index, start)?, to_byte_offset(index, end)?);
+    let source;
+    let pattern = match &pattern {
+      Either::A(str) => Pattern::String(str),
+      Either::B(reg) if reg.global() => {
+        source = to_rust_pattern(reg).map_err(to_napi_error)?;
+        Pattern::Regex(&source)
+      }
+      Either::B(_) => {
+        return Err(to_napi_error(Error::from_reason(
+          FmsErrType::Type,
+          "replaceAllInRange called with a non-global RegExp argument",
+        )));
+      }
+    };
+    self
+      .inner
+      .replace_all_in_range(start, end, pattern, &replacement)
+      .map_err(to_napi_error)?;
+    Ok(self)
+  }
+
+  #[napi]
+  pub fn replace_many(
+    &mut self,
+    replacements: HashMap<String, String>,
+    options: Option<JsReplaceManyOptions>,
+  ) -> Result<&Self> {
+    let replacements: Vec<(&str, &str)> = replacements
+      .iter()
+      .map(|(pattern, replacement)| (pattern.as_str(), replacement.as_str()))
+      .collect();
+    self
+      .inner
+      .replace_many(&replacements, options.map(|x| x.into()))
+      .map_err(to_napi_error)?;
+    Ok(self)
+  }
+
+  #[napi]
+  pub fn replace_identifiers(
+    &mut self,
+    replacements: HashMap<String, String>,
+    options: Option<JsIdentifierOptions>,
+  ) -> Result<&Self> {
+    let replacements: Vec<(&str, &str)> = replacements
+      .iter()
+      .map(|(name, replacement)| (name.as_str(), replacement.as_str()))
+      .collect();
+    self
+      .inner
+      .replace_identifiers(&replacements, options.map(|x| x.into()))
+      .map_err(to_napi_error)?;
+    Ok(self)
+  }
+
+  #[napi]
+  pub fn replace(
+    &mut self,
+    #[napi(ts_arg_type = "RegExp | string")] pattern: Either<String, JsRegExp>,
     replacement: Either<String, Function>,
+    options: Option<JsReplaceOptions>,
   ) -> Result<&Self> {
+    let target = to_replace_target(options).map_err(to_napi_error)?;
     match replacement {
       Either::A(replacement) => match pattern {
         Either::A(str) => {
           self
             .inner
-            .replace_by_string(str.as_str(), replacement.as_str())
-            .map_err(to_napi_error)?;
-        }
-        Either::B(reg) => {
-          self
-            .inner
-            .replace_by_regexp(reg.source.as_str(), replacement.as_str(), reg.global())
+            .replace_with_target(Pattern::String(&str), &replacement, false, target)
             .map_err(to_napi_error)?;
         }
+        Either::B(reg) => self.replace_regexp(&reg, &replacement, target)?,
       },
       Either::B(_) => {
         return Err(to_napi_error(Error::from_reason(
@@ -314,31 +840,29 @@ impl JsMagicString {
   #[napi]
   pub fn replace_all(
     &mut self,
-    #[napi(ts_arg_type = "RegExp | string")] pattern: Either<String, FmsRegex>,
+    #[napi(ts_arg_type = "RegExp | string")] pattern: Either<String, JsRegExp>,
     replacement: Either<String, Function>,
+    options: Option<JsReplaceOptions>,
   ) -> Result<&Self> {
+    let target = to_replace_target(options).map_err(to_napi_error)?;
     match replacement {
       Either::A(replacement) => match pattern {
         Either::A(pattern) => {
           self
             .inner
-            .replace_all_by_string(pattern.as_str(), replacement.as_str())
+            .replace_with_target(Pattern::String(&pattern), &replacement, true, target)
             .map_err(to_napi_error)?;
         }
         Either::B(reg) => {
-          let global = reg.global();
           // https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/String/replaceAll#pattern
           // > If pattern is a regex, then it must have the global (g) flag set, or a TypeError is thrown.
-          if !global {
+          if !reg.regex.global() {
             return Err(to_napi_error(Error::from_reason(
               FmsErrType::Type,
               "replaceAll called with a non-global RegExp argument",
             )));
           }
-          self
-            .inner
-            .replace_by_regexp(reg.source.as_str(), replacement.as_str(), global)
-            .map_err(to_napi_error)?;
+          self.replace_regexp(&reg, &replacement, target)?;
         }
       },
       Either::B(_) => {
@@ -352,3 +876,138 @@ impl JsMagicString {
     Ok(self)
   }
 }
+
+impl JsMagicString {
+  // `lastIndex` is read from and written back to the RegExp like `String.prototype.replace` does
+  fn replace_regexp(
+    &mut self,
+    reg: &JsRegExp,
+    replacement: &str,
+    target: ReplaceTarget,
+  ) -> Result<()> {
+    let mut state = to_regex_state(&reg.regex);
+    self
+      .inner
+      .replace_regex(
+        &to_rust_pattern(&reg.regex).map_err(to_napi_error)?,
+        replacement,
+        &mut state,
+        target,
+      )
+      .map_err(to_napi_error)?;
+    if state.global || state.sticky {
+      reg.set_last_index(state.last_index)?;
+    }
+    Ok(())
+  }
+
+  fn flush_warnings(&self, env: &Env) -> Result<()> {
+    self.warnings.report(env, self.inner.take_warnings())
+  }
+
+  // a new `MagicString` over the same original, e.g. a clone
+  fn derive(&self, inner: MagicString) -> JsMagicString {
+    JsMagicString {
+      indent_exclusion_ranges: self.indent_exclusion_ranges.clone(),
+      inner,
+      warnings: self.warnings.detached(),
+      offset: self.offset,
+      original_index: self.original_index.clone(),
+      generated_index: RefCell::default(),
+    }
+  }
+
+  fn original_index(&self) -> &Utf16Index {
+    self
+      .original_index
+      .get_or_init(|| Utf16Index::new(&self.inner.original))
+  }
+
+  fn generated_index(&self) -> Rc<Utf16Index> {
+    let edit_count = self.inner.edit_count();
+    let mut generated_index = self.generated_index.borrow_mut();
+    match &*generated_index {
+      Some((count, index)) if *count == edit_count => Rc::clone(index),
+      _ => {
+        let index = Rc::new(Utf16Index::new(&self.inner.to_string()));
+        *generated_index = Some((edit_count, Rc::clone(&index)));
+        index
+      }
+    }
+  }
+
+  // an index relative to `offset` as the byte index relative to the byte offset of `inner`
+  fn inner_index(&self, index: u32) -> Result<u32> {
+    let index = to_byte_offset(
+      self.original_index(),
+      i64::from(index) + i64::from(self.offset),
+    )?;
+    to_inner(index - i64::from(self.inner.offset()))
+  }
+
+  fn inner_range(&self, start: i32, end: i32) -> Result<(i32, i32)> {
+    let convert = |i: i32| {
+      let index = to_byte_offset(self.original_index(), i64::from(i) + i64::from(self.offset))?;
+      to_inner(index - i64::from(self.inner.offset()))
+    };
+    Ok((convert(start)?, convert(end)?))
+  }
+
+  fn to_indent_options(&self, options: Option<JsIndentOptions>) -> Result<Option<IndentOptions>> {
+    let mut options: Option<IndentOptions> = options.map(|x| x.into());
+    if let Some(options) = &mut options {
+      options.exclude = options
+        .exclude
+        .take()
+        .map(|ranges| to_byte_ranges(self.original_index(), ranges))
+        .transpose()?;
+    }
+    Ok(options)
+  }
+}
+
+fn out_of_range(index: impl std::fmt::Display) -> napi::Error {
+  to_napi_error(Error::from_reason(
+    FmsErrType::Range,
+    format!("Index {} is out of range or inside a character", index).as_str(),
+  ))
+}
+
+fn to_byte_index(index: &Utf16Index, i: u32) -> Result<u32> {
+  index.byte_index(i).ok_or_else(|| out_of_range(i))
+}
+
+// negative indices count from the end like in magic-string, before they are converted
+fn to_byte_offset<T>(index: &Utf16Index, i: T) -> Result<T>
+where
+  T: Into<i64> + TryFrom<i64> + Copy + std::fmt::Display,
+{
+  let len = i64::from(index.len());
+  let mut value = i.into();
+  if value < 0 && len > 0 {
+    value = value.rem_euclid(len);
+  }
+  u32::try_from(value)
+    .ok()
+    .and_then(|value| index.byte_index(value))
+    .and_then(|value| T::try_from(i64::from(value)).ok())
+    .ok_or_else(|| out_of_range(i))
+}
+
+fn to_inner<T: TryFrom<i64>>(index: i64) -> Result<T> {
+  T::try_from(index).map_err(|_| out_of_range(index))
+}
+
+fn to_byte_indices(index: &Utf16Index, indices: Vec<u32>) -> Result<Vec<u32>> {
+  indices
+    .into_iter()
+    .map(|i| to_byte_index(index, i))
+    .collect()
+}
+
+fn to_byte_ranges(index: &Utf16Index, ranges: Vec<Vec<u32>>) -> Result<Vec<Vec<u32>>> {
+  ranges
+    .into_iter()
+    .map(|range| to_byte_indices(index, range))
+    .collect()
+}
@@ -0,0 +1,193 @@
+use std::{cell::RefCell, ptr};
+
+use fast_magic_string::{
+  error::{Error, FmsErrType},
+  fms_sourcemap::{
+    mappings::{encode_mappings, Mappings},
+    DecodedMap, SourceMap,
+  },
+};
+use napi::{
+  bindgen_prelude::{CallbackInfo, FromNapiValue, JavaScriptClassExt, This, ToNapiValue, TypeName},
+  sys, Env, JsError, Property, PropertyAttributes, Result, ValueType,
+};
+
+use crate::{error::to_napi_error, types::JsSourceMapJson};
+
+enum LazyMappings {
+  Decoded(Mappings),
+  Encoded(String),
+}
+
+// magic-string's `SourceMap`, the fields are own properties of the instance so they can be
+// mutated, spread and enumerated like on a plain object, `mappings` is an accessor that
+// encodes the mappings the first time it is read
+#[napi(js_name = "SourceMap")]
+pub struct JsSourceMap {
+  mappings: RefCell<LazyMappings>,
+}
+
+#[napi]
+impl JsSourceMap {
+  #[napi]
+  pub fn to_string(&self, this: This<JsSourceMapJson>) -> Result<String> {
+    to_source_map(this.object)
+      .to_string()
+      .map_err(|_| to_napi_error(Error::new(FmsErrType::JSON)))
+  }
+
+  #[napi]
+  pub fn to_url(&self, this: This<JsSourceMapJson>) -> Result<String> {
+    to_source_map(this.object)
+      .to_url()
+      .map_err(|_| to_napi_error(Error::new(FmsErrType::JSON)))
+  }
+
+  #[napi(js_name = "toJSON")]
+  pub fn to_json(&self, this: This<JsSourceMapJson>) -> JsSourceMapJson {
+    this.object
+  }
+}
+
+impl JsSourceMap {
+  fn encoded_mappings(&self) -> Result<String> {
+    let mut mappings = self.mappings.borrow_mut();
+    if let LazyMappings::Decoded(decoded) = &*mappings {
+      let encoded = encode_mappings(decoded).map_err(|err| to_napi_error(err.into()))?;
+      *mappings = LazyMappings::Encoded(encoded);
+    }
+    match &*mappings {
+      LazyMappings::Encoded(encoded) => Ok(encoded.clone()),
+      LazyMappings::Decoded(_) => unreachable!(),
+    }
+  }
+}
+
+fn to_source_map(json: JsSourceMapJson) -> SourceMap {
+  SourceMap {
+    version: json.version,
+    file: json.file,
+    source_root: json.source_root,
+    sources: json.sources,
+    sources_content: json.sources_content,
+    names: json.names,
+    mappings: json.mappings,
+    x_google_ignoreList: json.x_google_ignore_list,
+  }
+}
+
+// a map on its way to JS, becomes a `SourceMap` instance, `map.mappings` is left empty
+pub struct SourceMapObject {
+  map: DecodedMap,
+  mappings: LazyMappings,
+}
+
+impl From<DecodedMap> for SourceMapObject {
+  fn from(mut map: DecodedMap) -> Self {
+    let mappings = LazyMappings::Decoded(std::mem::take(&mut map.mappings));
+    SourceMapObject { map, mappings }
+  }
+}
+
+// already encoded, e.g. on the libuv thread pool
+impl From<SourceMap> for SourceMapObject {
+  fn from(source_map: SourceMap) -> Self {
+    SourceMapObject {
+      map: DecodedMap {
+        version: source_map.version,
+        file: source_map.file,
+        source_root: source_map.source_root,
+        sources: source_map.sources,
+        sources_content: source_map.sources_content,
+        names: source_map.names,
+        mappings: vec![],
+        x_google_ignoreList: source_map.x_google_ignoreList,
+      },
+      mappings: LazyMappings::Encoded(source_map.mappings),
+    }
+  }
+}
+
+impl TypeName for SourceMapObject {
+  fn type_name() -> &'static str {
+    "SourceMap"
+  }
+
+  fn value_type() -> ValueType {
+    ValueType::Object
+  }
+}
+
+impl ToNapiValue for SourceMapObject {
+  unsafe fn to_napi_value(raw_env: sys::napi_env, val: Self) -> Result<sys::napi_value> {
+    let env = Env::from_raw(raw_env);
+    let SourceMapObject { map, mappings } = val;
+    let instance = JsSourceMap {
+      mappings: RefCell::new(mappings),
+    }
+    .into_instance(&env)?;
+
+    // in the order magic-string assigns them, missing values are `undefined` like there
+    let mut object = instance.as_object(&env);
+    object.set_named_property("version", map.version)?;
+    object.set_named_property("file", Undefinable(map.file))?;
+    if let Some(source_root) = map.source_root {
+      object.set_named_property("sourceRoot", source_root)?;
+    }
+    object.set_named_property("sources", map.sources)?;
+    object.set_named_property("sourcesContent", Undefinable(map.sources_content))?;
+    object.set_named_property("names", map.names)?;
+    object.define_properties(&[Property::new("mappings")?
+      .with_getter(get_mappings)
+      .with_setter(set_mappings)
+      .with_property_attributes(
+        PropertyAttributes::Enumerable | PropertyAttributes::Configurable,
+      )])?;
+    if let Some(x_google_ignore_list) = map.x_google_ignoreList {
+      object.set_named_property("x_google_ignoreList", x_google_ignore_list)?;
+    }
+    Ok(instance.value)
+  }
+}
+
+unsafe extern "C" fn get_mappings(
+  env: sys::napi_env,
+  info: sys::napi_callback_info,
+) -> sys::napi_value {
+  let result = CallbackInfo::<0>::new(env, info, None, false)
+    .and_then(|mut cb| cb.unwrap_borrow::<JsSourceMap>())
+    .and_then(|map| map.encoded_mappings())
+    .and_then(|mappings| unsafe { String::to_napi_value(env, mappings) });
+  unwrap_or_throw(env, result)
+}
+
+unsafe extern "C" fn set_mappings(
+  env: sys::napi_env,
+  info: sys::napi_callback_info,
+) -> sys::napi_value {
+  let result = CallbackInfo::<1>::new(env, info, Some(1), false).and_then(|mut cb| {
+    let mappings = unsafe { String::from_napi_value(env, cb.get_arg(0))? };
+    *cb.unwrap_borrow::<JsSourceMap>()?.mappings.borrow_mut() = LazyMappings::Encoded(mappings);
+    Ok(ptr::null_mut())
+  });
+  unwrap_or_throw(env, result)
+}
+
+// `None` as `undefined` instead of `null`
+struct Undefinable<T>(Option<T>);
+
+impl<T: ToNapiValue> ToNapiValue for Undefinable<T> {
+  unsafe fn to_napi_value(env: sys::napi_env, val: Self) -> Result<sys::napi_value> {
+    match val.0 {
+      Some(val) => unsafe { T::to_napi_value(env, val) },
+      None => unsafe { <()>::to_napi_value(env, ()) },
+    }
+  }
+}
+
+fn unwrap_or_throw(env: sys::napi_env, result: Result<sys::napi_value>) -> sys::napi_value {
+  result.unwrap_or_else(|err| {
+    unsafe { JsError::from(err).throw_into(env) };
+    ptr::null_mut()
+  })
+}
@@ -0,0 +1,84 @@
+use fast_magic_string::{
+  fms_sourcemap::SourceMap, snapshot::MagicStringSnapshot, warning::Warning, GenerateMapOptions,
+  MagicString,
+};
+use napi::{Env, Result, Task};
+
+use crate::{
+  error::to_napi_error, source_map::SourceMapObject, types::JsGenerateResult, warnings::WarningSink,
+};
+
+// the mappings are encoded on the libuv thread pool, from a snapshot taken when the task
+// was created, so later edits do not affect the result, warnings raised there are handed
+// back with the output and reported on the main thread
+pub struct GenerateMapTask {
+  snapshot: Option<MagicStringSnapshot>,
+  options: Option<GenerateMapOptions>,
+  warnings: WarningSink,
+}
+
+impl GenerateMapTask {
+  pub fn new(
+    snapshot: MagicStringSnapshot,
+    options: Option<GenerateMapOptions>,
+    warnings: WarningSink,
+  ) -> Self {
+    Self {
+      snapshot: Some(snapshot),
+      options,
+      warnings,
+    }
+  }
+}
+
+impl Task for GenerateMapTask {
+  type Output = (SourceMap, Vec<Warning>);
+  type JsValue = SourceMapObject;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    let s = MagicString::from_snapshot(self.snapshot.take().unwrap());
+    let map = s.generate_map(self.options.take()).map_err(to_napi_error)?;
+    Ok((map, s.take_warnings()))
+  }
+
+  fn resolve(&mut self, env: Env, (map, warnings): Self::Output) -> Result<Self::JsValue> {
+    self.warnings.report(&env, warnings)?;
+    Ok(map.into())
+  }
+}
+
+pub struct GenerateTask {
+  snapshot: Option<MagicStringSnapshot>,
+  options: Option<GenerateMapOptions>,
+  warnings: WarningSink,
+}
+
+impl GenerateTask {
+  pub fn new(
+    snapshot: MagicStringSnapshot,
+    options: Option<GenerateMapOptions>,
+    warnings: WarningSink,
+  ) -> Self {
+    Self {
+      snapshot: Some(snapshot),
+      options,
+      warnings,
+    }
+  }
+}
+
+impl Task for GenerateTask {
+  type Output = ((String, SourceMap), Vec<Warning>);
+  type JsValue = JsGenerateResult;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    let s = MagicString::from_snapshot(self.snapshot.take().unwrap());
+    let result = s.generate(self.options.take()).map_err(to_napi_error)?;
+    Ok((result, s.take_warnings()))
+  }
+
+  fn resolve(&mut self, env: Env, (result, warnings): Self::Output) -> Result<Self::JsValue> {
+    self.warnings.report(&env, warnings)?;
+    Ok(result.into())
+  }
+}
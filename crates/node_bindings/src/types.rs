@@ -1,8 +1,20 @@
 use fast_magic_string::{
+  chunk::ChunkView,
+  error::{Error, FmsErrType},
   fms_sourcemap::{DecodedMap, SourceMap},
-  GenerateMapOptions, IndentExclusionRanges, IndentOptions, MagicStringOptions, OverwriteOptions,
+  position::MappedPosition,
+  result::Result,
+  search::{
+    FindMatch, FindOptions, IdentifierOptions, MatchRange, RegexState, ReplaceManyOptions,
+    ReplaceTarget,
+  },
+  utils::Utf16Index,
+  GenerateMapOptions, IndentExclusionRanges, IndentOptions, IndentStyle, MagicStringOptions,
+  OverwriteOptions, ReindentOptions,
 };
-use napi::Either;
+use napi::{Either, Env, JsObject, JsString};
+
+use crate::source_map::SourceMapObject;
 
 #[napi(object)]
 pub struct JsIndentOptions {
@@ -19,12 +31,45 @@ impl From<JsIndentOptions> for IndentOptions {
   }
 }
 
+#[napi(object)]
+pub struct JsReindentOptions {
+  pub start: Option<i32>,
+  pub end: Option<i32>,
+  pub exclude: Option<Vec<Vec<u32>>>,
+}
+
+impl From<JsReindentOptions> for ReindentOptions {
+  fn from(js_reindent_options: JsReindentOptions) -> Self {
+    ReindentOptions {
+      start: js_reindent_options.start,
+      end: js_reindent_options.end,
+      exclude: js_reindent_options.exclude,
+    }
+  }
+}
+
+// `'tab'` or a number of spaces
+pub fn to_indent_style(style: Either<String, u32>) -> Result<IndentStyle> {
+  match style {
+    Either::A(s) if s == "tab" => Ok(IndentStyle::Tab),
+    Either::A(_) => Err(Error::from_reason(
+      FmsErrType::Type,
+      "indent style must be 'tab' or a number of spaces",
+    )),
+    Either::B(width) => Ok(IndentStyle::Spaces(width)),
+  }
+}
+
 #[napi(object)]
 #[derive(Clone)]
 pub struct JsMagicStringOptions {
   pub filename: Option<String>,
   pub indent_exclusion_ranges: Option<Either<Vec<u32>, Vec<Vec<u32>>>>,
   pub ignore_list: Option<bool>,
+  pub one_based_lines: Option<bool>,
+  pub offset: Option<u32>,
+  /// Keep warnings for `takeWarnings()` instead of passing them to `process.emitWarning`
+  pub collect_warnings: Option<bool>,
 }
 
 impl From<JsMagicStringOptions> for MagicStringOptions {
@@ -42,6 +87,8 @@ impl From<JsMagicStringOptions> for MagicStringOptions {
       filename: js_magic_string_options.filename,
       indent_exclusion_ranges,
       ignore_list: js_magic_string_options.ignore_list,
+      one_based_lines: js_magic_string_options.one_based_lines,
+      offset: js_magic_string_options.offset,
     }
   }
 }
@@ -85,14 +132,275 @@ impl From<JsOverwriteOptions> for OverwriteOptions {
   }
 }
 
+#[napi(object)]
+pub struct JsPosition {
+  pub line: u32,
+  pub column: u32,
+}
+
+impl From<JsPosition> for (u32, u32) {
+  fn from(js_position: JsPosition) -> Self {
+    (js_position.line, js_position.column)
+  }
+}
+
+impl From<(u32, u32)> for JsPosition {
+  fn from((line, column): (u32, u32)) -> Self {
+    JsPosition { line, column }
+  }
+}
+
+#[napi(object)]
+pub struct JsMappedPosition {
+  #[napi(ts_type = "'exact' | 'insideEdit' | 'removed'")]
+  pub kind: String,
+  pub index: Option<u32>,
+  pub start: Option<u32>,
+  pub end: Option<u32>,
+}
+
+// `index` turns the byte offsets on the side `mapped_position` is on into JS string indices
+pub fn to_mapped_position(mapped_position: MappedPosition, index: &Utf16Index) -> JsMappedPosition {
+  match mapped_position {
+    MappedPosition::Exact(at) => JsMappedPosition {
+      kind: "exact".to_string(),
+      index: Some(index.utf16_index(at)),
+      start: None,
+      end: None,
+    },
+    MappedPosition::InsideEdit { start, end } => JsMappedPosition {
+      kind: "insideEdit".to_string(),
+      index: None,
+      start: Some(index.utf16_index(start)),
+      end: Some(index.utf16_index(end)),
+    },
+    MappedPosition::Removed { at } => JsMappedPosition {
+      kind: "removed".to_string(),
+      index: Some(index.utf16_index(at)),
+      start: None,
+      end: None,
+    },
+  }
+}
+
+#[napi(object)]
+pub struct JsChunk {
+  pub start: u32,
+  pub end: u32,
+  pub original: String,
+  pub intro: String,
+  pub content: String,
+  pub outro: String,
+  pub edited: bool,
+  pub store_name: bool,
+  pub generated_offset: u32,
+}
+
+pub fn to_chunk(chunk: ChunkView, original: &Utf16Index, generated: &Utf16Index) -> JsChunk {
+  JsChunk {
+    start: original.utf16_index(chunk.start),
+    end: original.utf16_index(chunk.end),
+    original: chunk.original,
+    intro: chunk.intro,
+    content: chunk.content,
+    outro: chunk.outro,
+    edited: chunk.edited,
+    store_name: chunk.store_name,
+    generated_offset: generated.utf16_index(chunk.generated_offset),
+  }
+}
+
+#[napi(object)]
+pub struct JsFindOptions {
+  pub generated: Option<bool>,
+}
+
+impl From<JsFindOptions> for FindOptions {
+  fn from(js_find_options: JsFindOptions) -> Self {
+    FindOptions {
+      generated: js_find_options.generated,
+    }
+  }
+}
+
+#[napi(object)]
+pub struct JsReplaceOptions {
+  #[napi(ts_type = "'original' | 'generated'")]
+  pub target: Option<String>,
+}
+
+pub fn to_replace_target(options: Option<JsReplaceOptions>) -> Result<ReplaceTarget> {
+  match options.and_then(|o| o.target).as_deref() {
+    None | Some("original") => Ok(ReplaceTarget::Original),
+    Some("generated") => Ok(ReplaceTarget::Generated),
+    Some(_) => Err(Error::from_reason(
+      FmsErrType::Type,
+      "`target` option must be 'original' or 'generated'",
+    )),
+  }
+}
+
+pub fn to_regex_state(regex: &fms_regexp::FmsRegex) -> RegexState {
+  RegexState {
+    global: regex.global(),
+    sticky: regex.sticky(),
+    last_index: regex.last_index,
+  }
+}
+
+// JS syntax in, `fms_regexp::CompiledRegex` syntax out
+pub fn to_rust_pattern(regex: &fms_regexp::FmsRegex) -> Result<String> {
+  Ok(regex.to_rust_pattern()?)
+}
+
+#[napi(object)]
+pub struct JsReplaceManyOptions {
+  pub word_boundary: Option<bool>,
+}
+
+impl From<JsReplaceManyOptions> for ReplaceManyOptions {
+  fn from(js_replace_many_options: JsReplaceManyOptions) -> Self {
+    ReplaceManyOptions {
+      word_boundary: js_replace_many_options.word_boundary,
+    }
+  }
+}
+
+#[napi(object)]
+pub struct JsIdentifierOptions {
+  pub identifier_chars: Option<String>,
+  pub skip_member_access: Option<bool>,
+  pub skip_property_keys: Option<bool>,
+}
+
+impl From<JsIdentifierOptions> for IdentifierOptions {
+  fn from(js_identifier_options: JsIdentifierOptions) -> Self {
+    IdentifierOptions {
+      identifier_chars: js_identifier_options.identifier_chars,
+      skip_member_access: js_identifier_options.skip_member_access,
+      skip_property_keys: js_identifier_options.skip_property_keys,
+    }
+  }
+}
+
+#[napi(object)]
+pub struct JsMatchRange {
+  pub start: Option<u32>,
+  pub end: Option<u32>,
+  pub text: String,
+}
+
+fn to_match_range(range: MatchRange, index: &Utf16Index) -> JsMatchRange {
+  JsMatchRange {
+    start: range.start.map(|i| index.utf16_index(i)),
+    end: range.end.map(|i| index.utf16_index(i)),
+    text: range.text,
+  }
+}
+
+#[napi(object)]
+pub struct JsFindMatch {
+  pub start: Option<u32>,
+  pub end: Option<u32>,
+  pub text: String,
+  pub captures: Vec<Option<JsMatchRange>>,
+}
+
+// `index` turns byte offsets into the original into JS string indices
+pub fn to_find_match(find_match: FindMatch, index: &Utf16Index) -> JsFindMatch {
+  JsFindMatch {
+    start: find_match.start.map(|i| index.utf16_index(i)),
+    end: find_match.end.map(|i| index.utf16_index(i)),
+    text: find_match.text,
+    captures: find_match
+      .captures
+      .into_iter()
+      .map(|capture| capture.map(|c| to_match_range(c, index)))
+      .collect(),
+  }
+}
+
+// a `RegExpMatchArray`, `indices` only when the RegExp has the `d` flag, `offsets` turns the
+// byte offsets into `input` into JS string indices
+pub fn to_match_array(
+  env: &Env,
+  find_match: FindMatch,
+  names: &[Option<String>],
+  input: JsString,
+  offsets: &Utf16Index,
+  has_indices: bool,
+) -> napi::Result<JsObject> {
+  let groups = names.iter().any(|name| name.is_some());
+  let mut array = env.create_array_with_length(find_match.captures.len() + 1)?;
+  let mut indices = env.create_array_with_length(find_match.captures.len() + 1)?;
+  let mut group_values = env.create_object()?;
+  let mut group_indices = env.create_object()?;
+
+  let whole = MatchRange {
+    start: find_match.start,
+    end: find_match.end,
+    text: find_match.text,
+  };
+  let ranges = std::iter::once(Some(whole)).chain(find_match.captures);
+  for (i, range) in ranges.enumerate() {
+    // `set_element` only takes JS values
+    let index = i.to_string();
+    let name = i
+      .checked_sub(1)
+      .and_then(|i| names.get(i))
+      .and_then(|name| name.as_deref());
+    match range {
+      Some(range) => {
+        let bounds = vec![
+          offsets.utf16_index(range.start.unwrap_or(0)),
+          offsets.utf16_index(range.end.unwrap_or(0)),
+        ];
+        if let Some(name) = name {
+          group_values.set_named_property(name, range.text.as_str())?;
+          group_indices.set_named_property(name, bounds.clone())?;
+        }
+        array.set_named_property(&index, range.text)?;
+        indices.set_named_property(&index, bounds)?;
+      }
+      None => {
+        if let Some(name) = name {
+          group_values.set_named_property(name, ())?;
+          group_indices.set_named_property(name, ())?;
+        }
+        array.set_named_property(&index, ())?;
+        indices.set_named_property(&index, ())?;
+      }
+    }
+  }
+
+  array.set_named_property("index", offsets.utf16_index(find_match.start.unwrap_or(0)))?;
+  array.set_named_property("input", input)?;
+  if groups {
+    array.set_named_property("groups", group_values)?;
+  } else {
+    array.set_named_property("groups", ())?;
+  }
+  if has_indices {
+    if groups {
+      indices.set_named_property("groups", group_indices)?;
+    } else {
+      indices.set_named_property("groups", ())?;
+    }
+    array.set_named_property("indices", indices)?;
+  }
+  Ok(array)
+}
+
+#[allow(dead_code)]
 #[napi(object)]
 pub struct FmsRegex {
   pub global: Option<bool>,
   pub rule: String,
 }
 
+// what `SourceMap#toJSON` returns, the keys `JSON.stringify` would write
 #[napi(object)]
-pub struct JsSourceMap {
+pub struct JsSourceMapJson {
   pub version: u8,
   pub file: Option<String>,
   pub source_root: Option<String>,
@@ -100,12 +408,13 @@ pub struct JsSourceMap {
   pub sources_content: Option<Vec<String>>,
   pub names: Vec<String>,
   pub mappings: String,
-  pub x_google_ignoreList: Option<Vec<u8>>,
+  #[napi(js_name = "x_google_ignoreList")]
+  pub x_google_ignore_list: Option<Vec<u8>>,
 }
 
-impl From<SourceMap> for JsSourceMap {
+impl From<SourceMap> for JsSourceMapJson {
   fn from(source_map: SourceMap) -> Self {
-    JsSourceMap {
+    JsSourceMapJson {
       version: source_map.version,
       file: source_map.file,
       source_root: source_map.source_root,
@@ -113,7 +422,24 @@ impl From<SourceMap> for JsSourceMap {
       sources_content: source_map.sources_content,
       names: source_map.names,
       mappings: source_map.mappings,
-      x_google_ignoreList: source_map.x_google_ignoreList,
+      x_google_ignore_list: source_map.x_google_ignoreList,
+    }
+  }
+}
+
+// the result of `generate` and `generateAsync`, named like the `SourceMap` it holds
+#[napi(object, object_from_js = false, js_name = "GenerateResult")]
+pub struct JsGenerateResult {
+  pub code: String,
+  #[napi(ts_type = "SourceMap")]
+  pub map: SourceMapObject,
+}
+
+impl<T: Into<SourceMapObject>> From<(String, T)> for JsGenerateResult {
+  fn from((code, map): (String, T)) -> Self {
+    JsGenerateResult {
+      code,
+      map: map.into(),
     }
   }
 }
@@ -127,7 +453,7 @@ pub struct JsDecodedMap {
   pub sources_content: Option<Vec<String>>,
   pub names: Vec<String>,
   pub mappings: Vec<Vec<Vec<i64>>>,
-  pub x_google_ignoreList: Option<Vec<u8>>,
+  pub x_google_ignore_list: Option<Vec<u8>>,
 }
 
 impl From<DecodedMap> for JsDecodedMap {
@@ -140,7 +466,7 @@ impl From<DecodedMap> for JsDecodedMap {
       sources_content: decoded_map.sources_content,
       names: decoded_map.names,
       mappings: decoded_map.mappings,
-      x_google_ignoreList: decoded_map.x_google_ignoreList,
+      x_google_ignore_list: decoded_map.x_google_ignoreList,
     }
   }
 }
@@ -0,0 +1,114 @@
+use std::{
+  collections::HashSet,
+  sync::{Arc, LazyLock, Mutex},
+};
+
+use fast_magic_string::warning::{Warning, WarningCode};
+use napi::{bindgen_prelude::Function, Env, JsObject, Result};
+
+// like node's own deprecations, each warning is emitted once per process, keyed on the code
+// and the API so messages that quote their arguments don't repeat or pile up, so later
+// occurrences are dropped even when they come from another instance or other arguments,
+// `collectWarnings` keeps every one of them
+static EMITTED: LazyLock<Mutex<HashSet<(WarningCode, &'static str)>>> =
+  LazyLock::new(|| Mutex::new(HashSet::new()));
+
+#[napi(object)]
+pub struct JsWarning {
+  pub code: String,
+  pub message: String,
+}
+
+impl From<Warning> for JsWarning {
+  fn from(warning: Warning) -> Self {
+    JsWarning {
+      code: warning.code.as_str().to_string(),
+      message: warning.message,
+    }
+  }
+}
+
+#[napi(object)]
+struct JsEmitWarningOptions {
+  #[napi(js_name = "type")]
+  pub warning_type: String,
+  pub code: String,
+}
+
+// forwards to `process.emitWarning`, so `--no-warnings` and `process.on('warning')` apply
+fn emit_warnings(env: &Env, warnings: Vec<Warning>) -> Result<()> {
+  if warnings.is_empty() {
+    return Ok(());
+  }
+  let emit_warning = env
+    .get_global()?
+    .get_named_property::<JsObject>("process")?
+    .get_named_property::<Function<(String, JsEmitWarningOptions), ()>>("emitWarning")?;
+
+  for warning in warnings {
+    if !EMITTED
+      .lock()
+      .unwrap_or_else(|e| e.into_inner())
+      .insert((warning.code, warning.api))
+    {
+      continue;
+    }
+    let warning_type = match warning.code {
+      WarningCode::DeprecatedApi => "DeprecationWarning",
+      _ => "Warning",
+    };
+    emit_warning.call((
+      warning.message,
+      JsEmitWarningOptions {
+        warning_type: warning_type.to_string(),
+        code: warning.code.as_str().to_string(),
+      },
+    ))?;
+  }
+  Ok(())
+}
+
+// where the warnings of a `MagicString` end up, `Collect` keeps them for `takeWarnings`, it
+// is shared with the async tasks so warnings raised on the thread pool are kept there too
+#[derive(Clone)]
+pub enum WarningSink {
+  Emit,
+  Collect(Arc<Mutex<Vec<Warning>>>),
+}
+
+impl WarningSink {
+  pub fn new(collect: bool) -> Self {
+    if collect {
+      WarningSink::Collect(Arc::default())
+    } else {
+      WarningSink::Emit
+    }
+  }
+
+  // an empty sink of the same kind, for a new `MagicString` derived from this one
+  pub fn detached(&self) -> Self {
+    WarningSink::new(matches!(self, WarningSink::Collect(_)))
+  }
+
+  pub fn report(&self, env: &Env, warnings: Vec<Warning>) -> Result<()> {
+    match self {
+      WarningSink::Emit => emit_warnings(env, warnings),
+      WarningSink::Collect(collected) => {
+        collected
+          .lock()
+          .unwrap_or_else(|e| e.into_inner())
+          .extend(warnings);
+        Ok(())
+      }
+    }
+  }
+
+  pub fn take(&self) -> Vec<Warning> {
+    match self {
+      WarningSink::Emit => vec![],
+      WarningSink::Collect(collected) => {
+        std::mem::take(&mut *collected.lock().unwrap_or_else(|e| e.into_inner()))
+      }
+    }
+  }
+}
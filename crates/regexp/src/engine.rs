@@ -0,0 +1,316 @@
+use std::{fmt, ops::Index, sync::Arc};
+
+#[derive(Debug, Clone)]
+pub struct RegexError {
+  pub message: String,
+}
+
+impl fmt::Display for RegexError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.message)
+  }
+}
+
+impl From<fancy_regex::Error> for RegexError {
+  fn from(err: fancy_regex::Error) -> Self {
+    RegexError {
+      message: err.to_string(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Match<'h> {
+  haystack: &'h str,
+  start: usize,
+  end: usize,
+}
+
+impl<'h> Match<'h> {
+  pub fn start(&self) -> usize {
+    self.start
+  }
+
+  pub fn end(&self) -> usize {
+    self.end
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.start == self.end
+  }
+
+  pub fn as_str(&self) -> &'h str {
+    &self.haystack[self.start..self.end]
+  }
+}
+
+// group 0 is the whole match
+#[derive(Debug, Clone)]
+pub struct Captures<'h> {
+  groups: Vec<Option<Match<'h>>>,
+}
+
+impl<'h> Captures<'h> {
+  pub fn get(&self, i: usize) -> Option<Match<'h>> {
+    self.groups.get(i).copied().flatten()
+  }
+
+  pub fn len(&self) -> usize {
+    self.groups.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.groups.is_empty()
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = Option<Match<'h>>> + '_ {
+    self.groups.iter().copied()
+  }
+}
+
+impl Index<usize> for Captures<'_> {
+  type Output = str;
+
+  fn index(&self, i: usize) -> &str {
+    self
+      .get(i)
+      .map(|m| m.as_str())
+      .unwrap_or_else(|| panic!("no group at index '{}'", i))
+  }
+}
+
+// the `regex` crate when it accepts the pattern, a backtracking engine for lookaround and
+// backreferences otherwise
+#[derive(Debug, Clone)]
+pub enum CompiledRegex {
+  Fast(regex::Regex),
+  // `fancy_regex::Regex` copies its program on clone
+  Backtracking(Arc<fancy_regex::Regex>),
+}
+
+impl CompiledRegex {
+  pub fn new(pattern: &str) -> Result<Self, RegexError> {
+    match regex::Regex::new(pattern) {
+      Ok(re) => Ok(CompiledRegex::Fast(re)),
+      Err(_) => fancy_regex::Regex::new(pattern)
+        .map(|re| CompiledRegex::Backtracking(Arc::new(re)))
+        .map_err(|err| RegexError {
+          message: format!("Invalid regular expression /{}/: {}", pattern, err),
+        }),
+    }
+  }
+
+  pub fn is_backtracking(&self) -> bool {
+    matches!(self, CompiledRegex::Backtracking(_))
+  }
+
+  // one per group including the whole match, `None` for unnamed groups
+  pub fn capture_names(&self) -> Vec<Option<String>> {
+    match self {
+      CompiledRegex::Fast(re) => re
+        .capture_names()
+        .map(|name| name.map(str::to_string))
+        .collect(),
+      CompiledRegex::Backtracking(re) => re
+        .capture_names()
+        .map(|name| name.map(str::to_string))
+        .collect(),
+    }
+  }
+
+  pub fn is_match(&self, text: &str) -> Result<bool, RegexError> {
+    match self {
+      CompiledRegex::Fast(re) => Ok(re.is_match(text)),
+      CompiledRegex::Backtracking(re) => Ok(re.is_match(text)?),
+    }
+  }
+
+  // unlike slicing `haystack`, the text before `start` still counts for anchors and lookbehind
+  pub fn captures_at<'h>(
+    &self,
+    haystack: &'h str,
+    start: usize,
+  ) -> Result<Option<Captures<'h>>, RegexError> {
+    let to_match = |start: usize, end: usize| Match {
+      haystack,
+      start,
+      end,
+    };
+    Ok(match self {
+      CompiledRegex::Fast(re) => re.captures_at(haystack, start).map(|caps| Captures {
+        groups: caps
+          .iter()
+          .map(|m| m.map(|m| to_match(m.start(), m.end())))
+          .collect(),
+      }),
+      CompiledRegex::Backtracking(re) => {
+        re.captures_from_pos(haystack, start)?.map(|caps| Captures {
+          groups: caps
+            .iter()
+            .map(|m| m.map(|m| to_match(m.start(), m.end())))
+            .collect(),
+        })
+      }
+    })
+  }
+
+  // like `captures_at` with the JS sticky flag, the match must start at `start`
+  pub fn captures_sticky_at<'h>(
+    &self,
+    haystack: &'h str,
+    start: usize,
+  ) -> Result<Option<Captures<'h>>, RegexError> {
+    if !haystack.is_char_boundary(start) {
+      return Ok(None);
+    }
+    // the leftmost match starts at `start` whenever any match does
+    Ok(
+      self
+        .captures_at(haystack, start)?
+        .filter(|caps| caps.get(0).unwrap().start() == start),
+    )
+  }
+
+  // like `lastIndex` in JS, an empty match advances by one character
+  pub fn captures_all<'h>(
+    &self,
+    haystack: &'h str,
+    start: usize,
+    global: bool,
+  ) -> Result<Vec<Captures<'h>>, RegexError> {
+    self.collect_captures(haystack, start, global, false)
+  }
+
+  // every match starts where the previous one ended
+  pub fn captures_all_sticky<'h>(
+    &self,
+    haystack: &'h str,
+    start: usize,
+    global: bool,
+  ) -> Result<Vec<Captures<'h>>, RegexError> {
+    self.collect_captures(haystack, start, global, true)
+  }
+
+  fn collect_captures<'h>(
+    &self,
+    haystack: &'h str,
+    start: usize,
+    global: bool,
+    sticky: bool,
+  ) -> Result<Vec<Captures<'h>>, RegexError> {
+    let mut matches = vec![];
+    let mut pos = start;
+
+    while pos <= haystack.len() {
+      let captures = if sticky {
+        self.captures_sticky_at(haystack, pos)?
+      } else {
+        self.captures_at(haystack, pos)?
+      };
+      let Some(captures) = captures else {
+        break;
+      };
+      let m = captures.get(0).unwrap();
+      pos = if m.is_empty() {
+        m.end() + haystack[m.end()..].chars().next().map_or(1, char::len_utf8)
+      } else {
+        m.end()
+      };
+      matches.push(captures);
+      if !global {
+        break;
+      }
+    }
+    Ok(matches)
+  }
+
+  pub fn replace_all_with<F>(&self, text: &str, mut replacer: F) -> Result<String, RegexError>
+  where
+    F: FnMut(&Captures) -> String,
+  {
+    self.replace_with(text, true, &mut replacer)
+  }
+
+  // replaces the first match with `replacement` as is, `$` has no special meaning
+  pub fn replace(&self, text: &str, replacement: &str) -> Result<String, RegexError> {
+    self.replace_with(text, false, &mut |_| replacement.to_string())
+  }
+
+  fn replace_with(
+    &self,
+    text: &str,
+    global: bool,
+    replacer: &mut dyn FnMut(&Captures) -> String,
+  ) -> Result<String, RegexError> {
+    let mut replaced = String::with_capacity(text.len());
+    let mut last = 0;
+    for caps in self.captures_all(text, 0, global)? {
+      let m = caps.get(0).unwrap();
+      replaced.push_str(&text[last..m.start()]);
+      replaced.push_str(&replacer(&caps));
+      last = m.end();
+    }
+    replaced.push_str(&text[last..]);
+    Ok(replaced)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn picks_the_fast_engine_when_possible() {
+    assert!(!CompiledRegex::new(r"\brequire\(")
+      .unwrap()
+      .is_backtracking());
+    assert!(CompiledRegex::new(r"(?<!\.)\brequire\(")
+      .unwrap()
+      .is_backtracking());
+  }
+
+  #[test]
+  fn supports_lookaround_and_backreferences() {
+    let re = CompiledRegex::new(r"(?<!\.)\brequire\(").unwrap();
+    let found = re
+      .captures_all("a.require(x); require(y)", 0, true)
+      .unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].get(0).unwrap().start(), 14);
+
+    let re = CompiledRegex::new(r"(['\x22])(.*?)\1").unwrap();
+    let found = re.captures_all(r#"'a' "b'" 'c"#, 0, true).unwrap();
+    let quoted: Vec<&str> = found.iter().map(|caps| &caps[2]).collect();
+    assert_eq!(quoted, vec!["a", "b'"]);
+  }
+
+  #[test]
+  fn keeps_context_before_start() {
+    let re = CompiledRegex::new(r"(?<=a)b").unwrap();
+    assert_eq!(re.captures_all("ab", 1, true).unwrap().len(), 1);
+    let re = CompiledRegex::new(r"^b").unwrap();
+    assert!(re.captures_all("ab", 1, true).unwrap().is_empty());
+  }
+
+  #[test]
+  fn sticky_matches_only_at_the_position() {
+    let re = CompiledRegex::new(r"a").unwrap();
+    assert!(re.captures_sticky_at("ba", 0).unwrap().is_none());
+    assert!(re.captures_sticky_at("ba", 1).unwrap().is_some());
+    assert_eq!(re.captures_all_sticky("aaba", 0, true).unwrap().len(), 2);
+  }
+
+  #[test]
+  fn advances_past_empty_matches() {
+    let re = CompiledRegex::new(r"x*").unwrap();
+    assert_eq!(
+      re.replace_all_with("xaé", |_| "-".into()).unwrap(),
+      "--a-é-"
+    );
+  }
+
+  #[test]
+  fn reports_invalid_patterns() {
+    assert!(CompiledRegex::new(r"(").is_err());
+  }
+}
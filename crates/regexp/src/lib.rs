@@ -1,10 +1,20 @@
 use std::fmt::{Debug, Error};
 
+mod engine;
+#[cfg(feature = "napi")]
 mod napi;
+mod translate;
+
+pub use engine::{Captures, CompiledRegex, Match, RegexError};
+#[cfg(feature = "napi")]
+pub use napi::JsRegExp;
+pub use regex::escape;
+pub use translate::translate;
 
 pub struct FmsRegex {
   pub flags: String,
   pub source: String,
+  pub last_index: u32,
 }
 
 impl Debug for FmsRegex {
@@ -12,6 +22,7 @@ impl Debug for FmsRegex {
     f.debug_struct("FmsRegex")
       .field("flags", &self.flags)
       .field("source", &self.source)
+      .field("last_index", &self.last_index)
       .finish()
   }
 }
@@ -26,6 +37,7 @@ impl FmsRegex {
     Ok(Self {
       flags: chars.into_iter().collect(),
       source: expr.to_string(),
+      last_index: 0,
     })
   }
 
@@ -36,4 +48,9 @@ impl FmsRegex {
   pub fn sticky(&self) -> bool {
     self.flags.contains('y')
   }
+
+  // `source` is ECMAScript syntax, this is the same pattern for `CompiledRegex`
+  pub fn to_rust_pattern(&self) -> Result<String, RegexError> {
+    translate(&self.source, &self.flags)
+  }
 }
@@ -43,9 +43,17 @@ impl FromNapiValue for FmsRegex {
     if js_object_type == "[object RegExp]" {
       let source = js_object.get_named_property::<String>("source")?;
       let flags = js_object.get_named_property::<String>("flags")?;
+      let last_index = js_object.get_named_property::<f64>("lastIndex")?;
 
-      Self::with_flags(&source, &flags)
-        .map_err(|err| napi::Error::new(napi::Status::InvalidArg, err.to_string()))
+      let mut regex = Self::with_flags(&source, &flags)
+        .map_err(|err| napi::Error::new(napi::Status::InvalidArg, err.to_string()))?;
+      // `ToLength`, anything odd becomes 0
+      regex.last_index = if last_index.is_finite() && last_index > 0.0 {
+        last_index.min(u32::MAX as f64) as u32
+      } else {
+        0
+      };
+      Ok(regex)
     } else {
       Err(napi::Error::new(
         napi::Status::ObjectExpected,
@@ -74,3 +82,43 @@ impl ToNapiValue for FmsRegex {
     Ok(regex.new_instance((source, flags))?.raw())
   }
 }
+
+// a RegExp argument that keeps hold of its JS object, so `lastIndex` can be written back,
+// only valid for the duration of the call it was passed to
+pub struct JsRegExp {
+  pub regex: FmsRegex,
+  env: napi::sys::napi_env,
+  object: napi::sys::napi_value,
+}
+
+impl JsRegExp {
+  pub fn set_last_index(&self, last_index: u32) -> napi::Result<()> {
+    let mut js_object = unsafe { JsObject::from_raw_unchecked(self.env, self.object) };
+    js_object.set_named_property("lastIndex", last_index)
+  }
+}
+
+impl ValidateNapiValue for JsRegExp {}
+
+impl TypeName for JsRegExp {
+  fn type_name() -> &'static str {
+    FmsRegex::type_name()
+  }
+
+  fn value_type() -> napi::ValueType {
+    FmsRegex::value_type()
+  }
+}
+
+impl FromNapiValue for JsRegExp {
+  unsafe fn from_napi_value(
+    env: napi::sys::napi_env,
+    napi_val: napi::sys::napi_value,
+  ) -> napi::Result<Self> {
+    Ok(Self {
+      regex: unsafe { FmsRegex::from_napi_value(env, napi_val)? },
+      env,
+      object: napi_val,
+    })
+  }
+}
@@ -0,0 +1,758 @@
+// Translates ECMAScript pattern syntax into the Rust `regex` dialect.
+//
+// The output targets the `regex` crate unless the pattern needs lookaround or
+// backreferences, in which case it targets `fancy-regex` and may use lookaround itself
+// to match JS semantics more closely.
+
+use crate::engine::RegexError;
+
+#[derive(Default, Clone, Copy)]
+struct Flags {
+  ignore_case: bool,
+  multiline: bool,
+  dot_all: bool,
+  unicode: bool,
+}
+
+impl Flags {
+  fn parse(flags: &str) -> Result<Self, RegexError> {
+    let mut parsed = Flags::default();
+    for flag in flags.chars() {
+      match flag {
+        'i' => parsed.ignore_case = true,
+        'm' => parsed.multiline = true,
+        's' => parsed.dot_all = true,
+        'u' | 'v' => parsed.unicode = true,
+        // matching state, not pattern syntax
+        'g' | 'y' | 'd' => {}
+        _ => return Err(error(format!("Invalid regular expression flag '{}'", flag))),
+      }
+    }
+    Ok(parsed)
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClassEscape {
+  Digit,
+  Word,
+  Space,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ClassItem {
+  Char(char),
+  Range(char, char),
+  Escape(ClassEscape, bool),
+  Property(String, bool),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+  Char(char),
+  Any,
+  LineStart,
+  LineEnd,
+  WordBoundary(bool),
+  Escape(ClassEscape, bool),
+  Property(String, bool),
+  Class(Vec<ClassItem>, bool),
+  // `(`, `(?:`, `(?<name>`, lookaround, the string is what opens the group in Rust syntax
+  Open(String),
+  Close,
+  Alternation,
+  // already in Rust syntax, `{1,2}?`
+  Quantifier(String),
+  Backreference(String),
+}
+
+const WORD: &str = "0-9A-Za-z_";
+const SPACE: &str = r"\t\n\x{B}\x{C}\r \x{A0}\x{1680}\x{2000}-\x{200A}\x{2028}\x{2029}\x{202F}\x{205F}\x{3000}\x{FEFF}";
+const LINE_TERMINATORS: &str = r"\n\r\x{2028}\x{2029}";
+
+fn error(message: String) -> RegexError {
+  RegexError { message }
+}
+
+pub fn translate(source: &str, flags: &str) -> Result<String, RegexError> {
+  let flags = Flags::parse(flags)?;
+  let tokens = Parser::new(source, flags).parse()?;
+  let backtracking = tokens.iter().any(|token| match token {
+    Token::Open(open) => {
+      open.starts_with("(?=")
+        || open.starts_with("(?!")
+        || open.starts_with("(?<=")
+        || open.starts_with("(?<!")
+    }
+    Token::Backreference(_) => true,
+    _ => false,
+  });
+
+  let mut pattern = String::with_capacity(source.len());
+  if flags.ignore_case {
+    pattern.push_str("(?i)");
+  }
+  for token in &tokens {
+    match token {
+      Token::Char(c) => push_char(&mut pattern, *c),
+      Token::Any if flags.dot_all => pattern.push_str("(?s:.)"),
+      Token::Any => {
+        pattern.push_str("[^");
+        pattern.push_str(LINE_TERMINATORS);
+        pattern.push(']');
+      }
+      Token::LineStart if !flags.multiline => pattern.push('^'),
+      Token::LineEnd if !flags.multiline => pattern.push('$'),
+      // every JS line terminator, `fancy-regex` does not know CRLF mode
+      Token::LineStart if backtracking => {
+        pattern.push_str(&format!("(?:^|(?<=[{}]))", LINE_TERMINATORS))
+      }
+      Token::LineEnd if backtracking => {
+        pattern.push_str(&format!("(?:$|(?=[{}]))", LINE_TERMINATORS))
+      }
+      // `\r` and `\n`, the closest the `regex` crate gets
+      Token::LineStart => pattern.push_str("(?mR:^)"),
+      Token::LineEnd => pattern.push_str("(?mR:$)"),
+      // JS word boundaries are ASCII only
+      Token::WordBoundary(negated) if backtracking => {
+        let (inside, outside) = if *negated { ("=", "!") } else { ("!", "=") };
+        pattern.push_str(&format!(
+          "(?:(?<=[{w}])(?{inside}[{w}])|(?<![{w}])(?{outside}[{w}]))",
+          w = WORD
+        ))
+      }
+      Token::WordBoundary(false) => pattern.push_str(r"(?-u:\b)"),
+      Token::WordBoundary(true) => pattern.push_str(r"(?-u:\B)"),
+      Token::Escape(escape, negated) => push_class_escape(&mut pattern, *escape, *negated),
+      Token::Property(name, negated) => push_property(&mut pattern, name, *negated),
+      Token::Class(items, negated) => push_class(&mut pattern, items, *negated),
+      Token::Open(open) => pattern.push_str(open),
+      Token::Close => pattern.push(')'),
+      Token::Alternation => pattern.push('|'),
+      Token::Quantifier(quantifier) => pattern.push_str(quantifier),
+      Token::Backreference(reference) => pattern.push_str(reference),
+    }
+  }
+  Ok(pattern)
+}
+
+fn push_char(pattern: &mut String, c: char) {
+  if c.is_ascii_punctuation() {
+    pattern.push('\\');
+    pattern.push(c);
+  } else if c.is_control() || (c.is_whitespace() && c != ' ') {
+    pattern.push_str(&format!(r"\x{{{:X}}}", c as u32));
+  } else {
+    pattern.push(c);
+  }
+}
+
+fn push_class_escape(pattern: &mut String, escape: ClassEscape, negated: bool) {
+  pattern.push('[');
+  if negated {
+    pattern.push('^');
+  }
+  pattern.push_str(match escape {
+    ClassEscape::Digit => "0-9",
+    ClassEscape::Word => WORD,
+    ClassEscape::Space => SPACE,
+  });
+  pattern.push(']');
+}
+
+fn push_property(pattern: &mut String, name: &str, negated: bool) {
+  pattern.push_str(if negated { r"\P{" } else { r"\p{" });
+  pattern.push_str(name);
+  pattern.push('}');
+}
+
+fn push_class(pattern: &mut String, items: &[ClassItem], negated: bool) {
+  if items.is_empty() {
+    // `[]` never matches, `[^]` matches anything
+    pattern.push_str(if negated {
+      "(?s:.)"
+    } else {
+      r"[^\x{0}-\x{10FFFF}]"
+    });
+    return;
+  }
+  pattern.push('[');
+  if negated {
+    pattern.push('^');
+  }
+  for item in items {
+    match item {
+      ClassItem::Char(c) => push_char(pattern, *c),
+      ClassItem::Range(from, to) => {
+        push_char(pattern, *from);
+        pattern.push('-');
+        push_char(pattern, *to);
+      }
+      // nested classes are a union in Rust
+      ClassItem::Escape(escape, negated) => push_class_escape(pattern, *escape, *negated),
+      ClassItem::Property(name, negated) => push_property(pattern, name, *negated),
+    }
+  }
+  pattern.push(']');
+}
+
+struct Parser<'a> {
+  chars: Vec<char>,
+  pos: usize,
+  flags: Flags,
+  source: &'a str,
+  capture_count: usize,
+  has_named_groups: bool,
+}
+
+impl<'a> Parser<'a> {
+  fn new(source: &'a str, flags: Flags) -> Self {
+    let chars: Vec<char> = source.chars().collect();
+    let (capture_count, has_named_groups) = count_groups(&chars);
+    Self {
+      chars,
+      pos: 0,
+      flags,
+      source,
+      capture_count,
+      has_named_groups,
+    }
+  }
+
+  fn error(&self, reason: &str) -> RegexError {
+    error(format!(
+      "Invalid regular expression /{}/: {}",
+      self.source, reason
+    ))
+  }
+
+  fn peek(&self) -> Option<char> {
+    self.chars.get(self.pos).copied()
+  }
+
+  fn peek_at(&self, offset: usize) -> Option<char> {
+    self.chars.get(self.pos + offset).copied()
+  }
+
+  fn eat(&mut self, s: &str) -> bool {
+    let len = s.chars().count();
+    if self.pos + len <= self.chars.len()
+      && s
+        .chars()
+        .eq(self.chars[self.pos..self.pos + len].iter().copied())
+    {
+      self.pos += len;
+      true
+    } else {
+      false
+    }
+  }
+
+  fn parse(mut self) -> Result<Vec<Token>, RegexError> {
+    let mut tokens = vec![];
+    let mut depth = 0;
+
+    while let Some(c) = self.peek() {
+      self.pos += 1;
+      let token = match c {
+        '\\' => self.parse_atom_escape()?,
+        '.' => Token::Any,
+        '^' => Token::LineStart,
+        '$' => Token::LineEnd,
+        '|' => Token::Alternation,
+        '(' => {
+          depth += 1;
+          Token::Open(self.parse_group_open()?)
+        }
+        ')' => {
+          if depth == 0 {
+            return Err(self.error("Unmatched ')'"));
+          }
+          depth -= 1;
+          Token::Close
+        }
+        '[' => self.parse_class()?,
+        '*' | '+' | '?' => {
+          let mut quantifier = c.to_string();
+          if self.eat("?") {
+            quantifier.push('?');
+          }
+          Token::Quantifier(quantifier)
+        }
+        '{' => match self.parse_braced_quantifier() {
+          Some(quantifier) => Token::Quantifier(quantifier),
+          None if self.flags.unicode => return Err(self.error("Lone quantifier brackets")),
+          None => Token::Char('{'),
+        },
+        '}' | ']' if self.flags.unicode => return Err(self.error("Lone quantifier brackets")),
+        _ => Token::Char(c),
+      };
+      if let Token::Quantifier(_) = token {
+        match tokens.last() {
+          None
+          | Some(Token::Quantifier(_))
+          | Some(Token::Alternation)
+          | Some(Token::Open(_))
+          | Some(Token::LineStart)
+          | Some(Token::LineEnd)
+          | Some(Token::WordBoundary(_)) => return Err(self.error("Nothing to repeat")),
+          _ => {}
+        }
+      }
+      tokens.push(token);
+    }
+
+    if depth != 0 {
+      return Err(self.error("Unterminated group"));
+    }
+    Ok(tokens)
+  }
+
+  // `{n}`, `{n,}`, `{n,m}` with an optional `?`, `None` leaves the position untouched
+  fn parse_braced_quantifier(&mut self) -> Option<String> {
+    let start = self.pos;
+    let digits = |parser: &mut Self| {
+      let from = parser.pos;
+      while parser.peek().is_some_and(|c| c.is_ascii_digit()) {
+        parser.pos += 1;
+      }
+      (parser.pos > from).then(|| parser.chars[from..parser.pos].iter().collect::<String>())
+    };
+
+    let Some(min) = digits(self) else {
+      self.pos = start;
+      return None;
+    };
+    let mut quantifier = concat(&["{", &min]);
+    if self.eat(",") {
+      quantifier.push(',');
+      if let Some(max) = digits(self) {
+        quantifier.push_str(&max);
+      }
+    }
+    if !self.eat("}") {
+      self.pos = start;
+      return None;
+    }
+    quantifier.push('}');
+    if self.eat("?") {
+      quantifier.push('?');
+    }
+    Some(quantifier)
+  }
+
+  fn parse_group_open(&mut self) -> Result<String, RegexError> {
+    if !self.eat("?") {
+      return Ok("(".to_string());
+    }
+    for open in ["?:", "?=", "?!", "?<=", "?<!"] {
+      if self.eat(&open[1..]) {
+        return Ok(concat(&["(", open]));
+      }
+    }
+    if self.eat("<") {
+      let name = self.parse_group_name()?;
+      return Ok(concat(&["(?P<", &name, ">"]));
+    }
+    Err(self.error("Invalid group"))
+  }
+
+  fn parse_group_name(&mut self) -> Result<String, RegexError> {
+    let mut name = String::new();
+    loop {
+      match self.peek() {
+        Some('>') => {
+          self.pos += 1;
+          break;
+        }
+        Some(c) if c.is_alphanumeric() || c == '_' || c == '$' => {
+          self.pos += 1;
+          name.push(c);
+        }
+        _ => return Err(self.error("Invalid capture group name")),
+      }
+    }
+    if name.is_empty() {
+      return Err(self.error("Invalid capture group name"));
+    }
+    Ok(name)
+  }
+
+  fn parse_atom_escape(&mut self) -> Result<Token, RegexError> {
+    let Some(c) = self.peek() else {
+      return Err(self.error("\\ at end of pattern"));
+    };
+    self.pos += 1;
+    Ok(match c {
+      'b' => Token::WordBoundary(false),
+      'B' => Token::WordBoundary(true),
+      'k' if self.flags.unicode || self.has_named_groups => {
+        if !self.eat("<") {
+          return Err(self.error("Invalid named reference"));
+        }
+        let name = self.parse_group_name()?;
+        Token::Backreference(concat(&[r"\k<", &name, ">"]))
+      }
+      '1'..='9' => {
+        let start = self.pos - 1;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+          self.pos += 1;
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        match digits.parse::<usize>() {
+          Ok(n) if n <= self.capture_count => Token::Backreference(concat(&[r"\", &digits])),
+          _ if self.flags.unicode => return Err(self.error("Invalid escape")),
+          // Annex B, a legacy octal escape or an identity escape
+          _ => {
+            self.pos = start;
+            Token::Char(self.parse_legacy_octal())
+          }
+        }
+      }
+      _ => match self.parse_character_escape(c, false)? {
+        Escaped::Char(c) => Token::Char(c),
+        Escaped::Class(escape, negated) => Token::Escape(escape, negated),
+        Escaped::Property(name, negated) => Token::Property(name, negated),
+      },
+    })
+  }
+
+  // `\0`-`\377`, `8` and `9` are identity escapes
+  fn parse_legacy_octal(&mut self) -> char {
+    let first = self.chars[self.pos];
+    self.pos += 1;
+    if !('0'..='7').contains(&first) {
+      return first;
+    }
+    let mut value = first.to_digit(8).unwrap();
+    let max_len = if first <= '3' { 3 } else { 2 };
+    for _ in 1..max_len {
+      match self.peek().and_then(|c| c.to_digit(8)) {
+        Some(digit) => {
+          value = value * 8 + digit;
+          self.pos += 1;
+        }
+        None => break,
+      }
+    }
+    char::from_u32(value).unwrap()
+  }
+
+  fn parse_hex(&mut self, len: usize) -> Option<u32> {
+    let digits: String = self.chars.get(self.pos..self.pos + len)?.iter().collect();
+    if digits.len() != len || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+      return None;
+    }
+    self.pos += len;
+    u32::from_str_radix(&digits, 16).ok()
+  }
+
+  fn parse_unicode_escape(&mut self) -> Result<Option<char>, RegexError> {
+    if self.flags.unicode && self.eat("{") {
+      let start = self.pos;
+      while self.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+        self.pos += 1;
+      }
+      let digits: String = self.chars[start..self.pos].iter().collect();
+      if !self.eat("}") {
+        return Err(self.error("Invalid Unicode escape"));
+      }
+      return u32::from_str_radix(&digits, 16)
+        .ok()
+        .and_then(char::from_u32)
+        .map(Some)
+        .ok_or_else(|| self.error("Invalid Unicode escape"));
+    }
+
+    let Some(unit) = self.parse_hex(4) else {
+      return Ok(None);
+    };
+    if (0xD800..0xDC00).contains(&unit) {
+      // a surrogate pair is one character in a Rust string
+      let start = self.pos;
+      if self.eat("\\u") {
+        if let Some(low) = self
+          .parse_hex(4)
+          .filter(|low| (0xDC00..0xE000).contains(low))
+        {
+          let c = 0x10000 + ((unit - 0xD800) << 10) + (low - 0xDC00);
+          return Ok(char::from_u32(c));
+        }
+      }
+      self.pos = start;
+    }
+    char::from_u32(unit)
+      .map(Some)
+      .ok_or_else(|| self.error("Lone surrogates can not be matched"))
+  }
+
+  fn parse_character_escape(&mut self, c: char, in_class: bool) -> Result<Escaped, RegexError> {
+    Ok(match c {
+      'd' => Escaped::Class(ClassEscape::Digit, false),
+      'D' => Escaped::Class(ClassEscape::Digit, true),
+      'w' => Escaped::Class(ClassEscape::Word, false),
+      'W' => Escaped::Class(ClassEscape::Word, true),
+      's' => Escaped::Class(ClassEscape::Space, false),
+      'S' => Escaped::Class(ClassEscape::Space, true),
+      'p' | 'P' if self.flags.unicode => {
+        if !self.eat("{") {
+          return Err(self.error("Invalid property name"));
+        }
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c != '}') {
+          self.pos += 1;
+        }
+        let name: String = self.chars[start..self.pos].iter().collect();
+        if !self.eat("}") || name.is_empty() {
+          return Err(self.error("Invalid property name"));
+        }
+        Escaped::Property(name, c == 'P')
+      }
+      't' => Escaped::Char('\t'),
+      'n' => Escaped::Char('\n'),
+      'v' => Escaped::Char('\u{B}'),
+      'f' => Escaped::Char('\u{C}'),
+      'r' => Escaped::Char('\r'),
+      '0' if !self.peek().is_some_and(|c| c.is_ascii_digit()) => Escaped::Char('\0'),
+      '0'..='9' if in_class && !self.flags.unicode => {
+        self.pos -= 1;
+        Escaped::Char(self.parse_legacy_octal())
+      }
+      'c' => match self.peek() {
+        Some(letter) if letter.is_ascii_alphabetic() => {
+          self.pos += 1;
+          Escaped::Char(char::from_u32(letter as u32 % 32).unwrap())
+        }
+        // Annex B, `\c` followed by anything else is a literal backslash
+        _ if !self.flags.unicode => {
+          self.pos -= 1;
+          Escaped::Char('\\')
+        }
+        _ => return Err(self.error("Invalid unicode escape")),
+      },
+      'x' => match self.parse_hex(2) {
+        Some(value) => Escaped::Char(char::from_u32(value).unwrap()),
+        None if !self.flags.unicode => Escaped::Char('x'),
+        None => return Err(self.error("Invalid escape")),
+      },
+      'u' => match self.parse_unicode_escape()? {
+        Some(c) => Escaped::Char(c),
+        None if !self.flags.unicode => Escaped::Char('u'),
+        None => return Err(self.error("Invalid Unicode escape")),
+      },
+      'b' if in_class => Escaped::Char('\u{8}'),
+      '-' if in_class => Escaped::Char('-'),
+      _ if self.flags.unicode && !c.is_ascii_punctuation() => {
+        return Err(self.error("Invalid escape"));
+      }
+      // an identity escape
+      _ => Escaped::Char(c),
+    })
+  }
+
+  fn parse_class(&mut self) -> Result<Token, RegexError> {
+    let negated = self.eat("^");
+    let mut items = vec![];
+
+    loop {
+      let Some(c) = self.peek() else {
+        return Err(self.error("Unterminated character class"));
+      };
+      self.pos += 1;
+      if c == ']' {
+        break;
+      }
+      let from = self.parse_class_atom(c)?;
+      // a range, unless `-` is the last character of the class
+      if self.peek() == Some('-') && self.peek_at(1).is_some_and(|c| c != ']') {
+        self.pos += 1;
+        let next = self.chars[self.pos];
+        self.pos += 1;
+        let to = self.parse_class_atom(next)?;
+        match (&from, &to) {
+          (ClassItem::Char(from), ClassItem::Char(to)) => {
+            if from > to {
+              return Err(self.error("Range out of order in character class"));
+            }
+            items.push(ClassItem::Range(*from, *to));
+          }
+          _ if self.flags.unicode => return Err(self.error("Invalid character class")),
+          // Annex B, `[\d-x]` is `\d`, `-` and `x`
+          _ => {
+            items.push(from);
+            items.push(ClassItem::Char('-'));
+            items.push(to);
+          }
+        }
+      } else {
+        items.push(from);
+      }
+    }
+    Ok(Token::Class(items, negated))
+  }
+
+  fn parse_class_atom(&mut self, c: char) -> Result<ClassItem, RegexError> {
+    if c != '\\' {
+      return Ok(ClassItem::Char(c));
+    }
+    let Some(escaped) = self.peek() else {
+      return Err(self.error("\\ at end of pattern"));
+    };
+    self.pos += 1;
+    Ok(match self.parse_character_escape(escaped, true)? {
+      Escaped::Char(c) => ClassItem::Char(c),
+      Escaped::Class(escape, negated) => ClassItem::Escape(escape, negated),
+      Escaped::Property(name, negated) => ClassItem::Property(name, negated),
+    })
+  }
+}
+
+enum Escaped {
+  Char(char),
+  Class(ClassEscape, bool),
+  Property(String, bool),
+}
+
+fn concat(parts: &[&str]) -> String {
+  parts.concat()
+}
+
+// capturing groups decide whether `\N` is a backreference or an octal escape
+fn count_groups(chars: &[char]) -> (usize, bool) {
+  let mut count = 0;
+  let mut named = false;
+  let mut in_class = false;
+  let mut i = 0;
+  while i < chars.len() {
+    match chars[i] {
+      '\\' => i += 1,
+      '[' => in_class = true,
+      ']' => in_class = false,
+      '(' if !in_class => {
+        if chars.get(i + 1) != Some(&'?') {
+          count += 1;
+        } else if chars.get(i + 2) == Some(&'<')
+          && !matches!(chars.get(i + 3), Some('=') | Some('!'))
+        {
+          count += 1;
+          named = true;
+        }
+      }
+      _ => {}
+    }
+    i += 1;
+  }
+  (count, named)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::CompiledRegex;
+
+  // (source, flags, haystack, the first match as JS reports it)
+  const CONFORMANCE: &[(&str, &str, &str, Option<&str>)] = &[
+    // character class escapes are ASCII in JS
+    (r"\d+", "", "x١٢3", Some("3")),
+    (r"\w+", "", "éa_1é", Some("a_1")),
+    (r"\W", "", "aé", Some("é")),
+    (r"[\d]+", "", "١2", Some("2")),
+    (r"[^\d\s]+", "", "12 ab", Some("ab")),
+    (r"\s", "", "a\u{85}\u{FEFF}", Some("\u{FEFF}")),
+    (r"\S+", "", " \u{85}a ", Some("\u{85}a")),
+    // word boundaries are ASCII in JS
+    (r"\bé", "", " é", None),
+    (r"\bé", "", "aé", Some("é")),
+    (r"a\b", "", "aé", Some("a")),
+    (r"\Ba", "", "ba", Some("a")),
+    // `.` stops at every line terminator unless `s` is set
+    (r"a.b", "", "a\rb", None),
+    (r"a.b", "", "a\u{2028}b", None),
+    (r"a.b", "s", "a\nb", Some("a\nb")),
+    (r"a[^]b", "", "a\nb", Some("a\nb")),
+    (r"a[]b", "", "ab", None),
+    // anchors
+    (r"^b", "", "a\nb", None),
+    (r"^b", "m", "a\nb", Some("b")),
+    (r"^b", "m", "a\rb", Some("b")),
+    (r"a$", "", "a\n", None),
+    (r"a$", "m", "a\r\n", Some("a")),
+    // named groups and backreferences
+    (r"(?<q>['x])a\k<q>", "", "'a' xax", Some("'a'")),
+    (r"(a)\1", "", "aa", Some("aa")),
+    (r"(?<=\$)\d+", "", "a1 $2", Some("2")),
+    (r"(?<!\.)foo", "", ".foo foo", Some("foo")),
+    (r"\bfoo(?=\()", "", "foo foo(", Some("foo")),
+    // character escapes
+    (r"\cJ", "", "a\nb", Some("\n")),
+    (r"\x41\u0042", "", "AB", Some("AB")),
+    (r"\u{1F600}", "u", "a😀", Some("😀")),
+    (r"\uD83D\uDE00", "", "a😀", Some("😀")),
+    (r"\u{2}", "", "uu", Some("uu")),
+    (r"\0", "", "a\0", Some("\0")),
+    (r"\101", "", "A", Some("A")),
+    (r"\/\.\-", "", "/.-", Some("/.-")),
+    (r"[\b]", "", "a\u{8}", Some("\u{8}")),
+    (r"\p{Script=Greek}+", "u", "aαβ", Some("αβ")),
+    (r"\p{L}", "", "p{L}", Some("p{L}")),
+    // characters that are special inside Rust classes only
+    (r"[[&~-]+", "", "a[&~-", Some("[&~-")),
+    (r"[a-c-e]+", "", "-e", Some("-e")),
+    (r"[\w-]+", "", "a-b", Some("a-b")),
+    // lone brackets and quantifiers
+    (r"a{", "", "a{", Some("a{")),
+    (r"a{1,x}", "", "a{1,x}", Some("a{1,x}")),
+    (r"a{2,}?", "", "aaa", Some("aa")),
+    (r"}]", "", "}]", Some("}]")),
+    (r"#a b", "", "#a b", Some("#a b")),
+    // flags
+    (r"abc", "i", "ABC", Some("ABC")),
+    (r"a", "gyd", "a", Some("a")),
+  ];
+
+  #[test]
+  fn conformance() {
+    for (source, flags, haystack, expected) in CONFORMANCE {
+      let pattern =
+        translate(source, flags).unwrap_or_else(|e| panic!("/{}/{}: {}", source, flags, e.message));
+      let re = CompiledRegex::new(&pattern)
+        .unwrap_or_else(|e| panic!("/{}/{} -> {}: {}", source, flags, pattern, e.message));
+      let found = re
+        .captures_at(haystack, 0)
+        .unwrap()
+        .map(|caps| caps.get(0).unwrap().as_str());
+      assert_eq!(
+        found, *expected,
+        "/{}/{} -> {} on {:?}",
+        source, flags, pattern, haystack
+      );
+    }
+  }
+
+  #[test]
+  fn uses_the_fast_engine_without_lookaround() {
+    for source in [r"\bfoo\b", r"^a$", r"(?<n>a)"] {
+      let pattern = translate(source, "m").unwrap();
+      assert!(!CompiledRegex::new(&pattern).unwrap().is_backtracking());
+    }
+  }
+
+  #[test]
+  fn rejects_invalid_patterns() {
+    for (source, flags) in [
+      ("(", ""),
+      (")", ""),
+      ("[a", ""),
+      ("*", ""),
+      ("a**", ""),
+      ("[z-a]", ""),
+      (r"\u{110000}", "u"),
+      (r"\q", "u"),
+      ("a{", "u"),
+      (r"\uD800", ""),
+      ("a", "x"),
+    ] {
+      assert!(translate(source, flags).is_err(), "/{}/{}", source, flags);
+    }
+  }
+}
@@ -29,4 +29,9 @@ impl BitSet {
       false
     }
   }
+
+  // whether any of `start..end` is set
+  pub fn has_any(&self, start: usize, end: usize) -> bool {
+    (start..end.min(self.bits.len() << 5)).any(|n| self.has(n))
+  }
 }
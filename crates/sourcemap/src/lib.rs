@@ -31,16 +31,25 @@ pub struct DecodedMap {
   pub x_google_ignoreList: Option<Vec<u8>>,
 }
 
+// serialized with the keys and in the order of magic-string's `SourceMap`
 #[allow(non_snake_case)]
 #[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct SourceMap {
   pub version: u8,
+  #[serde(skip_serializing_if = "Option::is_none")]
   pub file: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
   pub source_root: Option<String>,
   pub sources: Vec<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
   pub sources_content: Option<Vec<String>>,
   pub names: Vec<String>,
   pub mappings: String,
+  #[serde(
+    rename = "x_google_ignoreList",
+    skip_serializing_if = "Option::is_none"
+  )]
   pub x_google_ignoreList: Option<Vec<u8>>,
 }
 
@@ -97,6 +106,7 @@ impl SourceMap {
     })
   }
 
+  #[allow(clippy::result_unit_err)]
   pub fn to_string(&self) -> Result<String, ()> {
     let s = serde_json::to_string(self);
     match s {
@@ -105,6 +115,7 @@ impl SourceMap {
     }
   }
 
+  #[allow(clippy::result_unit_err)]
   pub fn to_url(&self) -> Result<String, ()> {
     let str = self.to_string()?;
     Ok(concat_string!(
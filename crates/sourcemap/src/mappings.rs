@@ -1,3 +1,5 @@
+use std::io;
+
 use crate::bit_set::BitSet;
 use crate::error::SourcemapError;
 
@@ -9,11 +11,15 @@ pub type Mappings = Vec<Line>;
 pub static SOURCE_INDEX: u8 = 0;
 
 pub struct MappingsFacade {
+  // lines that have not been flushed yet
   pub raw: Mappings,
+  flushed_lines: usize,
   generated_code_line: u32,
   generated_code_column: u32,
   hires: bool,
   sourcemap_locations: BitSet,
+  // the last generated character is a `\r`, it ends the line unless a `\n` follows
+  pending_cr: bool,
 }
 
 impl MappingsFacade {
@@ -22,11 +28,14 @@ impl MappingsFacade {
       generated_code_line: 0,
       generated_code_column: 0,
       hires,
-      raw: vec![],
+      raw: vec![Line::default()],
+      flushed_lines: 0,
       sourcemap_locations: BitSet::new(Some(sourcemap_locations)),
+      pending_cr: false,
     }
   }
 
+  #[allow(clippy::too_many_arguments)]
   pub fn add_mappings(
     &mut self,
     string_original: &str,
@@ -42,7 +51,7 @@ impl MappingsFacade {
       self.advance(chunk_intro);
     }
     if chunk_is_edited {
-      let lines: Vec<&str> = chunk_content.split('\n').collect();
+      let lines = self.lines(chunk_content);
       let lines_len = lines.len();
 
       for (index, &s) in lines.iter().enumerate() {
@@ -57,26 +66,26 @@ impl MappingsFacade {
           if name_index < usize::MAX {
             seg.push(name_index as i64);
           }
-          if let Some(line) = self.raw.get_mut(self.generated_code_line as usize) {
-            line.push(seg);
-          } else {
-            self.raw.push(vec![seg]);
-          }
+          self.push_segment(seg);
         }
         if index != lines_len - 1 {
-          self.generated_code_column = 0;
-          self.generated_code_line += 1;
+          self.new_line();
         } else {
-          self.generated_code_column += s.len() as u32;
+          self.generated_code_column += s.encode_utf16().count() as u32;
         }
       }
     } else {
-      let mut original_char_index = chunk_start as usize;
       let mut o_line = origin_line;
       let mut o_column = origin_column;
       let mut first = true;
 
-      while original_char_index < chunk_end as usize {
+      // columns count UTF-16 code units, indices are bytes
+      for (offset, c) in string_original[chunk_start as usize..chunk_end as usize].char_indices() {
+        let original_char_index = chunk_start as usize + offset;
+        if std::mem::take(&mut self.pending_cr) && c != '\n' {
+          self.new_line();
+          first = true;
+        }
         if self.hires || first || self.sourcemap_locations.has(original_char_index) {
           let seg: Seg = vec![
             self.generated_code_column.into(),
@@ -85,28 +94,32 @@ impl MappingsFacade {
             o_column.into(),
           ];
 
-          if let Some(line) = self.raw.get_mut(self.generated_code_line as usize) {
-            line.push(seg);
-          } else {
-            self.raw.push(vec![seg]);
-          }
+          self.push_segment(seg);
         }
-        match string_original.chars().nth(original_char_index).unwrap() {
+        match c {
           '\n' => {
             o_line += 1;
-            self.generated_code_line += 1;
             o_column = 0;
-            self.generated_code_column = 0;
+            self.new_line();
             first = true
           }
-          _ => {
-            o_column += 1;
+          '\r' => {
+            if string_original.as_bytes().get(original_char_index + 1) == Some(&b'\n') {
+              o_column += 1;
+            } else {
+              o_line += 1;
+              o_column = 0;
+            }
             self.generated_code_column += 1;
+            self.pending_cr = true;
+            first = false
+          }
+          _ => {
+            o_column += c.len_utf16() as u32;
+            self.generated_code_column += c.len_utf16() as u32;
             first = false
           }
         }
-
-        original_char_index += 1;
       }
     }
     if !chunk_outro.is_empty() {
@@ -118,77 +131,166 @@ impl MappingsFacade {
     if str.is_empty() {
       return;
     }
-    let lines: Vec<&str> = str.split("\n").collect();
-    if lines.len() > 1 {
-      for _ in 0..lines.len() - 1 {
-        self.generated_code_line += 1;
-        self.raw.push(Vec::default());
-      }
-      self.generated_code_column = 0;
+    let lines = self.lines(str);
+    for _ in 0..lines.len() - 1 {
+      self.new_line();
     }
 
-    self.generated_code_column += lines.last().unwrap().len() as u32;
+    self.generated_code_column += lines.last().unwrap().encode_utf16().count() as u32;
   }
 
-  pub fn get(&mut self) -> Mappings {
-    let mut source_index: i64 = 0;
-    let mut original_line: i64 = 0;
-    let mut original_column: i64 = 0;
+  // splits on `\n` and on a lone `\r`, a trailing `\r` is decided by what comes next
+  fn lines<'a>(&mut self, str: &'a str) -> Vec<&'a str> {
+    if str.is_empty() {
+      return vec![""];
+    }
+    if std::mem::take(&mut self.pending_cr) && !str.starts_with('\n') {
+      self.new_line();
+    }
+    let bytes = str.as_bytes();
+    let mut lines = vec![];
+    let mut start = 0;
+    for (i, &byte) in bytes.iter().enumerate() {
+      match byte {
+        b'\n' => {
+          lines.push(&str[start..i]);
+          start = i + 1;
+        }
+        b'\r' if i + 1 < bytes.len() && bytes[i + 1] != b'\n' => {
+          lines.push(&str[start..=i]);
+          start = i + 1;
+        }
+        _ => {}
+      }
+    }
+    lines.push(&str[start..]);
+    self.pending_cr = str.ends_with('\r');
+    lines
+  }
 
+  pub fn get(&mut self) -> Mappings {
+    let mut encoder = MappingsEncoder::default();
     self
       .raw
       .iter()
-      .map(|line| {
-        let mut generated_column: i64 = 0;
-
-        line
-          .iter()
-          .map(|segment| {
-            let generated_column_offset = segment[0] - generated_column;
-            let source_index_offset = segment[1] - source_index;
-            let original_line_offset = segment[2] - original_line;
-            let original_column_offset = segment[3] - original_column;
-
-            generated_column = segment[0];
-            source_index = segment[1];
-            original_line = segment[2];
-            original_column = segment[3];
-
-            vec![
-              generated_column_offset,
-              source_index_offset,
-              original_line_offset,
-              original_column_offset,
-            ]
-          })
-          .collect::<Line>()
-      })
+      .map(|line| encoder.relative(line))
       .collect::<Mappings>()
   }
+
+  // encodes the lines that can no longer receive segments
+  pub fn flush<W: io::Write>(
+    &mut self,
+    encoder: &mut MappingsEncoder,
+    writer: &mut W,
+  ) -> Result<(), SourcemapError> {
+    let complete = self.generated_code_line as usize - self.flushed_lines;
+    for line in self.raw.drain(..complete) {
+      encoder.write_line(&line, writer)?;
+    }
+    self.flushed_lines += complete;
+    Ok(())
+  }
+
+  pub fn finish<W: io::Write>(
+    &mut self,
+    encoder: &mut MappingsEncoder,
+    writer: &mut W,
+  ) -> Result<(), SourcemapError> {
+    self.flush(encoder, writer)?;
+    for line in self.raw.drain(..) {
+      encoder.write_line(&line, writer)?;
+    }
+    Ok(())
+  }
+
+  fn push_segment(&mut self, seg: Seg) {
+    let line = self.generated_code_line as usize - self.flushed_lines;
+    self.raw[line].push(seg);
+  }
+
+  fn new_line(&mut self) {
+    self.generated_code_line += 1;
+    self.generated_code_column = 0;
+    self.raw.push(Line::default());
+  }
+}
+
+// turns absolute segments into relative ones, line by line
+#[derive(Default)]
+pub struct MappingsEncoder {
+  source_index: i64,
+  original_line: i64,
+  original_column: i64,
+  name_index: i64,
+  lines: usize,
+}
+
+impl MappingsEncoder {
+  pub fn relative(&mut self, line: &Line) -> Line {
+    let mut generated_column: i64 = 0;
+
+    line
+      .iter()
+      .map(|segment| {
+        let generated_column_offset = segment[0] - generated_column;
+        let source_index_offset = segment[1] - self.source_index;
+        let original_line_offset = segment[2] - self.original_line;
+        let original_column_offset = segment[3] - self.original_column;
+
+        generated_column = segment[0];
+        self.source_index = segment[1];
+        self.original_line = segment[2];
+        self.original_column = segment[3];
+
+        let mut relative = vec![
+          generated_column_offset,
+          source_index_offset,
+          original_line_offset,
+          original_column_offset,
+        ];
+        if let Some(&name_index) = segment.get(4) {
+          relative.push(name_index - self.name_index);
+          self.name_index = name_index;
+        }
+        relative
+      })
+      .collect::<Line>()
+  }
+
+  pub fn write_line<W: io::Write>(
+    &mut self,
+    line: &Line,
+    writer: &mut W,
+  ) -> Result<(), SourcemapError> {
+    if self.lines > 0 {
+      writer.write_all(b";")?;
+    }
+    self.lines += 1;
+    write_line(&self.relative(line), writer)
+  }
+}
+
+fn write_line<W: io::Write>(line: &Line, writer: &mut W) -> Result<(), SourcemapError> {
+  for (seg_idx, seg) in line.iter().enumerate() {
+    if seg_idx != 0 {
+      writer.write_all(b",")?;
+    }
+    for item in seg.iter() {
+      // vlq need i64
+      vlq::encode(*item, writer)?;
+    }
+  }
+  Ok(())
 }
 
 pub fn encode_mappings(raw_mappings: &Mappings) -> Result<String, SourcemapError> {
   // see https://github.com/hoodie/concatenation_benchmarks-rs
-  let mut s = String::new();
+  let mut buf: Vec<u8> = vec![];
   for (line_idx, line) in raw_mappings.iter().enumerate() {
-    let mut line_s = String::new();
-    for (seg_idx, seg) in line.iter().enumerate() {
-      let mut seg_s = String::new();
-      for item in seg.iter() {
-        let mut vlq_output: Vec<u8> = vec![];
-        // vlq need i64
-        vlq::encode(item.to_owned(), &mut vlq_output)?;
-        seg_s.push_str(&String::from_utf8(vlq_output)?);
-      }
-      line_s.push_str(&seg_s);
-      if seg_idx != line.len() - 1 {
-        line_s.push(',');
-      }
-    }
-    s.push_str(&line_s);
-    if line_idx != raw_mappings.len() - 1 {
-      s.push(';');
+    if line_idx != 0 {
+      buf.push(b';');
     }
+    write_line(line, &mut buf)?;
   }
-  Ok(s)
+  Ok(String::from_utf8(buf)?)
 }
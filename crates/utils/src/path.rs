@@ -16,9 +16,10 @@ pub fn get_relative_path(from: &str, to: &str) -> String {
 
   let mut relative_path = Vec::new();
 
-  for _ in common_length..from_parts_clone.len() {
-    relative_path.push("..");
-  }
+  relative_path.extend(std::iter::repeat_n(
+    "..",
+    from_parts_clone.len() - common_length,
+  ));
 
   for part in &to_parts[common_length..] {
     relative_path.push(part);